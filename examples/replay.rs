@@ -0,0 +1,25 @@
+use std::sync::Arc;
+
+use logicline::{exporter::Auth, replay::Replay};
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let (Some(path), Some(password)) = (args.next(), args.next()) else {
+        panic!("Usage: replay <trace.jsonl> <password>");
+    };
+    let replay = Arc::new(Replay::from_trace(path).unwrap());
+    Arc::clone(&replay)
+        .exporter()
+        .with_auth(Auth::new().with_basic("replay", password))
+        .with_control()
+        .spawn(("0.0.0.0", 9001))
+        .unwrap();
+    replay.play();
+
+    println!("Open browser to http://localhost:9001 to view the replay (user: replay)");
+    println!("Control the replay with POST requests to /replay/[play|pause|seek|speed]");
+
+    loop {
+        std::thread::park();
+    }
+}
@@ -12,13 +12,43 @@ export interface Step {
 
 export interface Line {
   name: string;
+  input?: unknown;
   steps: (Step | Step[])[];
 }
 
+// Pass/fail counters of a line or a step
+export interface Counters {
+  executions: number;
+  passes: number;
+  fails: number;
+  transitions: number;
+  passed: boolean | null;
+  // UNIX timestamp, seconds
+  last_transition: number | null;
+}
+
+export interface StepStats extends Counters {
+  name: string;
+}
+
+export interface LineStats extends Counters {
+  // OR steps are flattened
+  steps: StepStats[];
+}
+
+export interface RackStats {
+  lines: {
+    [key: string]: LineStats;
+  };
+}
+
+// The snapshot format JSON Schema is served by the exporter at /schema
 export interface Snapshot {
+  version?: number;
   lines: {
     [key: string]: Line;
   };
+  stats?: RackStats;
 }
 
 export type BlockClickHandler = (step: Step) => void;
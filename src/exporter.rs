@@ -0,0 +1,841 @@
+use std::{
+    borrow::Cow,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response};
+
+use crate::{Rack, Snapshot, SnapshotFormatter};
+
+mod auth;
+#[cfg(feature = "exporter-compression")]
+mod compression;
+mod control;
+mod cors;
+mod events;
+mod state;
+#[cfg(feature = "exporter-ws")]
+mod ws;
+pub use auth::Auth;
+pub use cors::Cors;
+
+/// Default minimum interval between Server-Sent Events
+const DEFAULT_EVENTS_INTERVAL: Duration = Duration::from_millis(100);
+/// Default maximum number of streaming clients
+const DEFAULT_MAX_STREAMS: usize = 32;
+/// Default maximum number of long-polling `/state` requests
+const DEFAULT_MAX_WAITERS: usize = 64;
+#[cfg(feature = "exporter-ui")]
+const UI_HTML: &str = include_str!("../ll-default-view/dist/index.html");
+#[cfg(all(feature = "exporter-ui", feature = "exporter-compression"))]
+static UI_HTML_COMPRESSED: compression::Cache = compression::Cache::new();
+
+/// Read/write timeout of client connections (inherited from the listener). Idle keep-alive
+/// connections are closed after it, streaming connections are closed if the client stalls
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+type GenerationFn = Box<dyn Fn() -> u64 + Send + Sync>;
+type RackAccessFn = Box<dyn Fn(&mut dyn FnMut(&mut Rack)) + Send + Sync>;
+
+/// Exporter request handler
+pub(crate) trait Handler: Send + Sync + 'static {
+    /// Current (formatted) state snapshot
+    fn snapshot(&self) -> Snapshot;
+    /// Handles handler-specific requests, returns the request back if it has not been handled.
+    /// The CORS headers must be added to the responses (see [`respond`]). Modifying requests must
+    /// be checked with [`Exporter::check_control`]
+    fn handle(&self, request: Request, _exporter: &Exporter, _cors: &[Header]) -> Option<Request> {
+        Some(request)
+    }
+    /// Does the handler provide own control endpoints (see [`Exporter::with_control`])
+    fn has_control(&self) -> bool {
+        false
+    }
+}
+
+struct FnHandler<F>(F);
+
+impl<F> Handler for FnHandler<F>
+where
+    F: Fn() -> Snapshot + Send + Sync + 'static,
+{
+    fn snapshot(&self) -> Snapshot {
+        (self.0)()
+    }
+}
+
+/// Limits the number of concurrent connections of a kind, each one is served by own thread
+struct Slots {
+    max: usize,
+    used: AtomicUsize,
+}
+
+impl Slots {
+    fn new(max: usize) -> Self {
+        Self {
+            max,
+            used: AtomicUsize::new(0),
+        }
+    }
+    /// Takes a slot, `None` if all the slots are used. The slot is released when the guard is
+    /// dropped
+    fn acquire(self: &Arc<Self>) -> Option<SlotGuard> {
+        self.used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                (used < self.max).then_some(used + 1)
+            })
+            .ok()?;
+        Some(SlotGuard(self.clone()))
+    }
+}
+
+struct SlotGuard(Arc<Slots>);
+
+impl Drop for SlotGuard {
+    fn drop(&mut self) {
+        self.0.used.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A rack behind a lock, which can be used as an exporter snapshot source (see
+/// [`Exporter::from_rack`])
+pub trait SharedRack: Send + Sync + 'static {
+    /// Creates a snapshot of the rack state
+    fn snapshot(&self) -> Snapshot;
+    /// Current rack generation (see [`Rack::generation`])
+    fn generation(&self) -> u64;
+    /// Current rack statistics generation (see [`Rack::stats_generation`])
+    fn stats_generation(&self) -> u64;
+    /// Modifies the rack (used by the control endpoints, see [`Exporter::with_control`])
+    fn modify(&self, f: &mut dyn FnMut(&mut Rack));
+}
+
+impl SharedRack for std::sync::Mutex<Rack> {
+    fn snapshot(&self) -> Snapshot {
+        self.lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .snapshot()
+    }
+    fn generation(&self) -> u64 {
+        self.lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .generation()
+    }
+    fn stats_generation(&self) -> u64 {
+        self.lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .stats_generation()
+    }
+    fn modify(&self, f: &mut dyn FnMut(&mut Rack)) {
+        f(&mut self
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner));
+    }
+}
+
+impl SharedRack for std::sync::RwLock<Rack> {
+    fn snapshot(&self) -> Snapshot {
+        self.read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .snapshot()
+    }
+    fn generation(&self) -> u64 {
+        self.read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .generation()
+    }
+    fn stats_generation(&self) -> u64 {
+        self.read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .stats_generation()
+    }
+    fn modify(&self, f: &mut dyn FnMut(&mut Rack)) {
+        f(&mut self
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner));
+    }
+}
+
+impl SharedRack for rtsc::pi::Mutex<Rack> {
+    fn snapshot(&self) -> Snapshot {
+        self.lock().snapshot()
+    }
+    fn generation(&self) -> u64 {
+        self.lock().generation()
+    }
+    fn stats_generation(&self) -> u64 {
+        self.lock().stats_generation()
+    }
+    fn modify(&self, f: &mut dyn FnMut(&mut Rack)) {
+        f(&mut self.lock());
+    }
+}
+
+#[cfg(feature = "locking-default")]
+impl SharedRack for parking_lot::Mutex<Rack> {
+    fn snapshot(&self) -> Snapshot {
+        self.lock().snapshot()
+    }
+    fn generation(&self) -> u64 {
+        self.lock().generation()
+    }
+    fn stats_generation(&self) -> u64 {
+        self.lock().stats_generation()
+    }
+    fn modify(&self, f: &mut dyn FnMut(&mut Rack)) {
+        f(&mut self.lock());
+    }
+}
+
+#[cfg(feature = "locking-rt")]
+impl SharedRack for parking_lot_rt::Mutex<Rack> {
+    fn snapshot(&self) -> Snapshot {
+        self.lock().snapshot()
+    }
+    fn generation(&self) -> u64 {
+        self.lock().generation()
+    }
+    fn stats_generation(&self) -> u64 {
+        self.lock().stats_generation()
+    }
+    fn modify(&self, f: &mut dyn FnMut(&mut Rack)) {
+        f(&mut self.lock());
+    }
+}
+
+/// Exporter (HTTP server) builder.
+///
+/// The exporter serves snapshots of any source: a shared rack (see [`SharedRack`]) or a closure
+/// which returns snapshots:
+///
+/// ```rust,no_run
+/// use std::sync::{Arc, Mutex};
+/// use logicline::{Rack, exporter::Exporter};
+///
+/// let rack = Arc::new(Mutex::new(Rack::new().with_recording_enabled()));
+/// let exporter = Exporter::from_rack(rack.clone()).spawn(("127.0.0.1", 9001)).unwrap();
+/// // ...
+/// exporter.stop().unwrap();
+/// ```
+pub struct Exporter {
+    handler: Box<dyn Handler>,
+    formatter: Option<Box<dyn SnapshotFormatter>>,
+    auth: Option<Auth>,
+    generation: Option<GenerationFn>,
+    stats_generation: Option<GenerationFn>,
+    rack_access: Option<RackAccessFn>,
+    control: bool,
+    cors: Option<Cors>,
+    #[cfg(feature = "exporter-compression")]
+    compression: bool,
+    events_interval: Duration,
+    streams: Arc<Slots>,
+    waiters: Arc<Slots>,
+}
+
+impl Exporter {
+    /// Creates a new exporter builder with a closure as the snapshot source
+    pub fn new<F>(source: F) -> Self
+    where
+        F: Fn() -> Snapshot + Send + Sync + 'static,
+    {
+        Self::from_handler(FnHandler(source))
+    }
+    /// Creates a new exporter builder with a shared rack as the snapshot source (the rack
+    /// generations are used to detect changes)
+    pub fn from_rack<R: SharedRack>(rack: Arc<R>) -> Self {
+        let generation_rack = rack.clone();
+        let stats_generation_rack = rack.clone();
+        let access_rack = rack.clone();
+        Self::new(move || rack.snapshot())
+            .with_generation(move || generation_rack.generation())
+            .with_stats_generation(move || stats_generation_rack.stats_generation())
+            .with_rack_access(move |f| access_rack.modify(f))
+    }
+    pub(crate) fn from_handler<H: Handler>(handler: H) -> Self {
+        Self {
+            handler: Box::new(handler),
+            formatter: None,
+            auth: None,
+            generation: None,
+            stats_generation: None,
+            rack_access: None,
+            control: false,
+            cors: Some(Cors::default()),
+            #[cfg(feature = "exporter-compression")]
+            compression: false,
+            events_interval: DEFAULT_EVENTS_INTERVAL,
+            streams: Arc::new(Slots::new(DEFAULT_MAX_STREAMS)),
+            waiters: Arc::new(Slots::new(DEFAULT_MAX_WAITERS)),
+        }
+    }
+    /// Sets the snapshot formatter, which is applied to all served snapshots
+    pub fn with_formatter(mut self, formatter: Box<dyn SnapshotFormatter>) -> Self {
+        self.formatter = Some(formatter);
+        self
+    }
+    /// Enables authentication for all the exporter endpoints
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+    /// Sets the CORS configuration for all the exporter endpoints (by default, requests from any
+    /// origin are allowed)
+    pub fn with_cors(mut self, cors: Cors) -> Self {
+        self.cors = Some(cors);
+        self
+    }
+    /// Disables CORS: no CORS headers are sent, so browsers allow same-origin requests only
+    pub fn without_cors(mut self) -> Self {
+        self.cors = None;
+        self
+    }
+    /// Enables gzip/deflate compression of the state snapshots and the UI, negotiated with the
+    /// `Accept-Encoding` request header (small responses are not compressed)
+    #[cfg(feature = "exporter-compression")]
+    pub fn with_compression(mut self) -> Self {
+        self.compression = true;
+        self
+    }
+    /// Sets the generation source (see [`Rack::generation`]), which is used to detect changes.
+    /// If not set, the snapshots are compared
+    pub fn with_generation<G>(mut self, generation: G) -> Self
+    where
+        G: Fn() -> u64 + Send + Sync + 'static,
+    {
+        self.generation = Some(Box::new(generation));
+        self
+    }
+    /// Sets the statistics generation source (see [`Rack::stats_generation`]), which is used
+    /// together with the generation one to detect changes of snapshot statistics
+    pub fn with_stats_generation<G>(mut self, stats_generation: G) -> Self
+    where
+        G: Fn() -> u64 + Send + Sync + 'static,
+    {
+        self.stats_generation = Some(Box::new(stats_generation));
+        self
+    }
+    pub(crate) fn with_rack_access<F>(mut self, rack_access: F) -> Self
+    where
+        F: Fn(&mut dyn FnMut(&mut Rack)) + Send + Sync + 'static,
+    {
+        self.rack_access = Some(Box::new(rack_access));
+        self
+    }
+    /// Enables the control endpoints, which allow to modify the source remotely: `/control/*` for
+    /// racks (see [`Exporter::from_rack`]) and `/replay/*` for replays (see
+    /// [`crate::replay::Replay::exporter`]). Requires authentication (see [`Exporter::with_auth`])
+    /// and a source with control support, otherwise [`Exporter::spawn`] fails
+    pub fn with_control(mut self) -> Self {
+        self.control = true;
+        self
+    }
+    /// Sets the minimum interval between Server-Sent Events (`/events` endpoint), the default
+    /// is 100 ms. The changes are checked with the same interval (also for long-polling
+    /// `/state` requests)
+    pub fn with_events_interval(mut self, interval: Duration) -> Self {
+        self.events_interval = interval;
+        self
+    }
+    /// Sets the maximum number of streaming clients (`/events` and `/ws`), each one is served by own
+    /// thread. Clients above the limit get `503 Service Unavailable` responses. The default is 32
+    pub fn with_max_streams(mut self, max_streams: usize) -> Self {
+        self.streams = Arc::new(Slots::new(max_streams));
+        self
+    }
+    /// Sets the maximum number of concurrent long-polling `/state` requests (with the `wait`
+    /// parameter), each one is served by own thread. Requests above the limit get `503 Service
+    /// Unavailable` responses. The default is 64
+    pub fn with_max_waiters(mut self, max_waiters: usize) -> Self {
+        self.waiters = Arc::new(Slots::new(max_waiters));
+        self
+    }
+    /// Binds the address and spawns the exporter (HTTP server) thread
+    pub fn spawn<A: ToSocketAddrs>(
+        self,
+        addr: A,
+    ) -> Result<ExporterHandle, Box<dyn std::error::Error>> {
+        if self.control {
+            if self.auth.is_none() {
+                return Err("the control endpoints require authentication".into());
+            }
+            if self.rack_access.is_none() && !self.handler.has_control() {
+                return Err(
+                    "the control endpoints are not supported for the snapshot source".into(),
+                );
+            }
+        }
+        let listener = std::net::TcpListener::bind(addr)?;
+        let socket = socket2::SockRef::from(&listener);
+        socket.set_read_timeout(Some(IO_TIMEOUT))?;
+        socket.set_write_timeout(Some(IO_TIMEOUT))?;
+        let server =
+            Arc::new(tiny_http::Server::from_listener(listener, None).map_err(|e| e.to_string())?);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::Builder::new()
+            .name("ll-exporter".to_string())
+            .spawn({
+                let server = server.clone();
+                let stop = stop.clone();
+                move || Arc::new(self).serve(&server, &stop)
+            })?;
+        Ok(ExporterHandle {
+            server,
+            stop,
+            thread,
+        })
+    }
+    /// Creates a response with the body, compressed if enabled and accepted by the client. Static
+    /// bodies are compressed once, with the provided cache
+    #[cfg(feature = "exporter-compression")]
+    fn body_response(
+        &self,
+        request: &Request,
+        body: Cow<'static, [u8]>,
+        cache: Option<&compression::Cache>,
+    ) -> Response<std::io::Cursor<Vec<u8>>> {
+        if !self.compression {
+            return Response::from_data(body.into_owned());
+        }
+        let vary = header("Vary", "Accept-Encoding");
+        if let Some(encoding) = compression::ContentEncoding::negotiate(request) {
+            let compressed = match cache {
+                Some(cache) => cache.get(encoding, &body).map(<[u8]>::to_vec),
+                None => encoding.compress(&body),
+            };
+            if let Some(compressed) = compressed {
+                return Response::from_data(compressed)
+                    .with_header(header("Content-Encoding", encoding.name()))
+                    .with_header(vary);
+            }
+        }
+        Response::from_data(body.into_owned()).with_header(vary)
+    }
+    /// Creates a response with the body
+    #[cfg(not(feature = "exporter-compression"))]
+    fn body_response(body: Cow<'static, [u8]>) -> Response<std::io::Cursor<Vec<u8>>> {
+        Response::from_data(body.into_owned())
+    }
+    /// Checks a modifying control request, returns the response status code if the request is
+    /// refused: the control endpoints are disabled (404), the origin is not allowed (403) or the
+    /// request is not JSON (415)
+    pub(crate) fn check_control(&self, request: &Request) -> Result<(), u16> {
+        if !self.control {
+            return Err(404);
+        }
+        control::check(request, self.cors.as_ref())
+    }
+    /// Current generation and statistics generation, `None` if there is no generation source
+    fn generations(&self) -> Option<(u64, u64)> {
+        self.generation
+            .as_ref()
+            .map(|g| (g(), self.stats_generation.as_ref().map_or(0, |g| g())))
+    }
+    fn snapshot(&self) -> Snapshot {
+        let snapshot = self.handler.snapshot();
+        if let Some(ref formatter) = self.formatter {
+            formatter.format(snapshot)
+        } else {
+            snapshot
+        }
+    }
+    fn serve(self: &Arc<Self>, server: &tiny_http::Server, stop: &Arc<AtomicBool>) {
+        loop {
+            let request = match server.recv() {
+                Ok(request) => request,
+                Err(_) if stop.load(Ordering::SeqCst) => break,
+                Err(_) => {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                    continue;
+                }
+            };
+            let mut cors = self
+                .cors
+                .as_ref()
+                .map(|c| c.response_headers(&request))
+                .unwrap_or_default();
+            // preflight requests are answered for all the paths and are never authenticated
+            if request.method() == &Method::Options {
+                if let Some(ref c) = self.cors
+                    && !cors.is_empty()
+                {
+                    cors.extend(c.preflight_headers());
+                }
+                respond(request, Response::empty(204), &cors);
+                continue;
+            }
+            if let Some(ref auth) = self.auth
+                && !auth.is_authorized(&request)
+            {
+                let response =
+                    Response::empty(401).with_header(header("WWW-Authenticate", auth.challenge()));
+                respond(request, response, &cors);
+                continue;
+            }
+            let Some(request) = self.handler.handle(request, self, &cors) else {
+                continue;
+            };
+            self.handle(request, cors, stop);
+        }
+    }
+    fn handle(self: &Arc<Self>, request: Request, cors: Vec<Header>, stop: &Arc<AtomicBool>) {
+        if self.control
+            && let Some(rack_access) = self.rack_access.as_ref()
+            && (path(&request) == "/control" || path(&request).starts_with("/control/"))
+        {
+            control::handle(self, rack_access, request, &cors);
+            return;
+        }
+        if request.method() != &Method::Get {
+            respond(request, Response::empty(406), &cors);
+            return;
+        }
+        match path(&request) {
+            p if p == "/state" || p.starts_with("/state/") => {
+                state::handle(self, request, cors, stop);
+            }
+            "/events" => {
+                let Some(slot) = self.streams.acquire() else {
+                    respond(request, Response::empty(503), &cors);
+                    return;
+                };
+                events::spawn(self.clone(), request, cors, stop.clone(), slot);
+            }
+            #[cfg(feature = "exporter-ws")]
+            "/ws" if ws::is_upgrade(&request) => {
+                let Some(slot) = self.streams.acquire() else {
+                    respond(request, Response::empty(503), &cors);
+                    return;
+                };
+                ws::spawn(self.clone(), request, stop.clone(), slot);
+            }
+            "/metrics" => {
+                let response = Response::from_string(crate::metrics::render(&self.snapshot()))
+                    .with_header(header("Content-Type", crate::metrics::CONTENT_TYPE));
+                respond(request, response, &cors);
+            }
+            #[cfg(feature = "schema")]
+            "/schema" => {
+                let response = Response::from_data(
+                    serde_json::to_vec(&Snapshot::schema()).unwrap_or_default(),
+                )
+                .with_header(header("Content-Type", "application/schema+json"));
+                respond(request, response, &cors);
+            }
+            #[cfg(feature = "exporter-ui")]
+            "/" => {
+                let body = UI_HTML.as_bytes().into();
+                #[cfg(feature = "exporter-compression")]
+                let response = self.body_response(&request, body, Some(&UI_HTML_COMPRESSED));
+                #[cfg(not(feature = "exporter-compression"))]
+                let response = Self::body_response(body);
+                let response = response.with_header(header("Content-Type", "text/html"));
+                respond(request, response, &cors);
+            }
+            _ => respond(request, Response::empty(404), &cors),
+        }
+    }
+}
+
+/// Handle of a running exporter. Dropping the handle detaches the exporter thread (the server
+/// keeps running)
+pub struct ExporterHandle {
+    server: Arc<tiny_http::Server>,
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl ExporterHandle {
+    /// The address the server is bound to (useful when bound to port 0)
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+    /// Asks the server to stop, does not wait for the thread to finish
+    pub fn shutdown(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.server.unblock();
+    }
+    /// Has the server thread been finished
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+    /// Waits for the server thread to finish
+    pub fn join(self) -> std::thread::Result<()> {
+        self.thread.join()
+    }
+    /// Stops the server and waits for its thread to finish
+    pub fn stop(self) -> std::thread::Result<()> {
+        self.shutdown();
+        self.join()
+    }
+}
+
+/// Creates a response header
+///
+/// # Panics
+///
+/// Panics if the header name or value contains invalid characters
+pub(crate) fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
+/// Sends the response with additional (CORS) headers
+pub(crate) fn respond<R: std::io::Read>(
+    request: Request,
+    mut response: Response<R>,
+    headers: &[Header],
+) {
+    for h in headers {
+        response.add_header(h.clone());
+    }
+    let _ = request.respond(response);
+}
+
+/// Creates a JSON response
+pub(crate) fn json_response<T: Serialize>(value: &T) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_data(serde_json::to_vec(value).unwrap_or_default())
+        .with_header(header("Content-Type", "application/json"))
+}
+
+/// Does the line belong to the group. Line groups are name prefixes, separated with `/` (e.g.
+/// `boiler/fan_on` belongs to `boiler`)
+pub(crate) fn line_in_group(name: &str, group: &str) -> bool {
+    name.strip_prefix(group)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Decodes a percent-encoded URL component
+pub(crate) fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(b) = s
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            result.push(b);
+            i += 3;
+        } else {
+            result.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+/// Request URL path (without query parameters)
+pub(crate) fn path(request: &Request) -> &str {
+    let url = request.url();
+    url.split_once('?').map_or(url, |(path, _)| path)
+}
+
+/// Request URL query parameters
+pub(crate) fn query(request: &Request) -> impl Iterator<Item = (&str, &str)> {
+    request
+        .url()
+        .split_once('?')
+        .map(|(_, query)| query)
+        .unwrap_or_default()
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| p.split_once('=').unwrap_or((p, "")))
+}
+
+/// Snapshot encodings, supported by the exporter `/state` endpoint
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum StateEncoding {
+    Json,
+    #[cfg(feature = "cbor")]
+    Cbor,
+    #[cfg(feature = "msgpack")]
+    Msgpack,
+}
+
+impl StateEncoding {
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "application/json" | "application/*" | "*/*" => Some(StateEncoding::Json),
+            #[cfg(feature = "cbor")]
+            "application/cbor" => Some(StateEncoding::Cbor),
+            #[cfg(feature = "msgpack")]
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(StateEncoding::Msgpack)
+            }
+            _ => None,
+        }
+    }
+    /// Picks the supported encoding with the highest quality from the `Accept` header, JSON is
+    /// used by default
+    fn negotiate(request: &Request) -> Self {
+        let Some(accept) = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Accept"))
+            .map(|h| h.value.as_str())
+        else {
+            return StateEncoding::Json;
+        };
+        let mut result = StateEncoding::Json;
+        let mut result_q = 0.0;
+        for entry in accept.split(',') {
+            let mut parts = entry.split(';').map(str::trim);
+            let Some(encoding) = parts.next().and_then(Self::from_media_type) else {
+                continue;
+            };
+            let q = parts
+                .find_map(|p| p.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            // q=0 means "not acceptable"
+            if q <= 0.0 {
+                continue;
+            }
+            if q > result_q {
+                result = encoding;
+                result_q = q;
+            }
+        }
+        result
+    }
+    fn content_type(self) -> &'static str {
+        match self {
+            StateEncoding::Json => "application/json",
+            #[cfg(feature = "cbor")]
+            StateEncoding::Cbor => "application/cbor",
+            #[cfg(feature = "msgpack")]
+            StateEncoding::Msgpack => "application/msgpack",
+        }
+    }
+    /// Entity tag suffix, so the tags differ for different encodings
+    fn tag_suffix(self) -> &'static str {
+        match self {
+            StateEncoding::Json => "json",
+            #[cfg(feature = "cbor")]
+            StateEncoding::Cbor => "cbor",
+            #[cfg(feature = "msgpack")]
+            StateEncoding::Msgpack => "msgpack",
+        }
+    }
+    fn encode<T: Serialize>(self, value: &T) -> Vec<u8> {
+        match self {
+            StateEncoding::Json => serde_json::to_vec(value).unwrap_or_default(),
+            #[cfg(feature = "cbor")]
+            StateEncoding::Cbor => {
+                let mut buf = Vec::new();
+                let _ = ciborium::into_writer(value, &mut buf);
+                buf
+            }
+            #[cfg(feature = "msgpack")]
+            StateEncoding::Msgpack => rmp_serde::to_vec_named(value).unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::{
+        io::{Read, Write},
+        net::{SocketAddr, TcpStream},
+        sync::{Arc, Mutex},
+    };
+
+    use super::Exporter;
+    use crate::{Rack, action};
+
+    pub(crate) fn request(addr: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    pub(crate) fn get(addr: SocketAddr, path: &str) -> String {
+        request(
+            addr,
+            &format!(
+                "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                path
+            ),
+        )
+    }
+
+    #[test]
+    fn test_exporter() {
+        let rack = Arc::new(Mutex::new(Rack::new().with_recording_enabled()));
+        {
+            let mut rack = rack.lock().unwrap();
+            let mut processor = rack.processor();
+            processor
+                .line("fan_on", 31)
+                .then(action!("temp_high", |t| (t > 30).then_some(())));
+            rack.ingress(&mut processor);
+        }
+        let exporter = Exporter::from_rack(rack).spawn("127.0.0.1:0").unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let response = get(addr, "/state");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("\"temp_high\""));
+        assert!(get(addr, "/unknown").starts_with("HTTP/1.1 404"));
+        exporter.stop().unwrap();
+    }
+
+    #[cfg(any(feature = "cbor", feature = "msgpack"))]
+    #[test]
+    fn test_state_encodings() {
+        fn get_accept(addr: SocketAddr, accept: &str) -> (String, Vec<u8>) {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(
+                stream,
+                "GET /state HTTP/1.1\r\nHost: localhost\r\nAccept: {}\r\n\
+                 Connection: close\r\n\r\n",
+                accept
+            )
+            .unwrap();
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).unwrap();
+            let pos = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+            let head = String::from_utf8(response[..pos].to_vec()).unwrap();
+            (head, response[pos + 4..].to_vec())
+        }
+        let rack = Arc::new(Mutex::new(Rack::new().with_recording_enabled()));
+        {
+            let mut rack = rack.lock().unwrap();
+            let mut processor = rack.processor();
+            processor
+                .line("fan_on", 31)
+                .then(action!("temp_high", |t| (t > 30).then_some(())));
+            rack.ingress(&mut processor);
+        }
+        let exporter = Exporter::from_rack(rack).spawn("127.0.0.1:0").unwrap();
+        let addr = exporter.local_addr().unwrap();
+        #[cfg(feature = "cbor")]
+        {
+            let (head, body) = get_accept(addr, "application/json;q=0.5, application/cbor");
+            assert!(head.contains("Content-Type: application/cbor"));
+            assert!(head.contains("Vary: Accept"));
+            let snapshot = crate::Snapshot::from_cbor(&body).unwrap();
+            assert!(snapshot.line_state("fan_on").unwrap().passed());
+            // explicitly refused
+            let (head, _) = get_accept(addr, "application/cbor;q=0");
+            assert!(head.contains("Content-Type: application/json"));
+        }
+        #[cfg(feature = "msgpack")]
+        {
+            let (head, body) = get_accept(addr, "application/msgpack");
+            assert!(head.contains("Content-Type: application/msgpack"));
+            let snapshot = crate::Snapshot::from_msgpack(&body).unwrap();
+            assert!(snapshot.line_state("fan_on").unwrap().passed());
+        }
+        exporter.stop().unwrap();
+    }
+}
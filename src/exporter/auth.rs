@@ -0,0 +1,139 @@
+use tiny_http::Request;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> String {
+    let mut result = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or_default(),
+            chunk.get(2).copied().unwrap_or_default(),
+        ];
+        let n = u32::from(b[0]) << 16 | u32::from(b[1]) << 8 | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(char::from(
+                    BASE64_ALPHABET[(n >> (18 - i * 6)) as usize & 0x3f],
+                ));
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+/// Compares two byte slices in constant time (for the same length)
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Exporter authentication. Requests must contain `Authorization` header with one of the
+/// configured bearer tokens or HTTP basic credentials, otherwise `401 Unauthorized` is returned
+#[derive(Clone, Default)]
+pub struct Auth {
+    // expected `Authorization` header values
+    authorizations: Vec<String>,
+    basic: bool,
+}
+
+impl std::fmt::Debug for Auth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Auth")
+            .field("authorizations", &self.authorizations.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Auth {
+    /// Creates a new authentication config (without credentials, all requests are rejected)
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds a static bearer token (`Authorization: Bearer <token>`)
+    pub fn with_bearer_token(mut self, token: impl AsRef<str>) -> Self {
+        self.authorizations
+            .push(format!("Bearer {}", token.as_ref()));
+        self
+    }
+    /// Adds HTTP basic credentials (`Authorization: Basic <base64(user:password)>`). If basic
+    /// credentials are set, browsers ask for them when the UI is opened
+    pub fn with_basic(mut self, user: impl AsRef<str>, password: impl AsRef<str>) -> Self {
+        let credentials = format!("{}:{}", user.as_ref(), password.as_ref());
+        self.authorizations
+            .push(format!("Basic {}", base64(credentials.as_bytes())));
+        self.basic = true;
+        self
+    }
+    pub(crate) fn is_authorized(&self, request: &Request) -> bool {
+        let Some(value) = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Authorization"))
+            .map(|h| h.value.as_str().trim())
+        else {
+            return false;
+        };
+        // check all the values to keep the time constant
+        self.authorizations.iter().fold(false, |acc, a| {
+            constant_time_eq(a.as_bytes(), value.as_bytes()) | acc
+        })
+    }
+    /// `WWW-Authenticate` response header value
+    pub(crate) fn challenge(&self) -> &'static str {
+        if self.basic {
+            "Basic realm=\"logicline\", charset=\"UTF-8\""
+        } else {
+            "Bearer realm=\"logicline\""
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::Auth;
+    use crate::{
+        Rack,
+        exporter::{
+            Exporter,
+            test::{get, request},
+        },
+    };
+
+    #[test]
+    fn test_auth() {
+        assert_eq!(super::base64(b"user:password"), "dXNlcjpwYXNzd29yZA==");
+        assert_eq!(super::base64(b"ab"), "YWI=");
+        let rack = Arc::new(Mutex::new(Rack::new().with_recording_enabled()));
+        let exporter = Exporter::from_rack(rack)
+            .with_auth(
+                Auth::new()
+                    .with_bearer_token("secret")
+                    .with_basic("user", "password"),
+            )
+            .spawn("127.0.0.1:0")
+            .unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let response = get(addr, "/state");
+        assert!(response.starts_with("HTTP/1.1 401"));
+        assert!(response.contains("WWW-Authenticate: Basic realm=\"logicline\""));
+        let get_with = |authorization: &str| {
+            request(
+                addr,
+                &format!(
+                    "GET /state HTTP/1.1\r\nHost: localhost\r\nAuthorization: {}\r\n\
+                     Connection: close\r\n\r\n",
+                    authorization
+                ),
+            )
+        };
+        assert!(get_with("Bearer secret").starts_with("HTTP/1.1 200"));
+        assert!(get_with("Bearer wrong").starts_with("HTTP/1.1 401"));
+        assert!(get_with("Basic dXNlcjpwYXNzd29yZA==").starts_with("HTTP/1.1 200"));
+        exporter.stop().unwrap();
+    }
+}
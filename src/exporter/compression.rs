@@ -0,0 +1,220 @@
+use std::{io::Write as _, sync::OnceLock};
+
+use flate2::{
+    Compression,
+    write::{GzEncoder, ZlibEncoder},
+};
+use tiny_http::Request;
+
+/// Smaller bodies are not compressed
+const MIN_SIZE: usize = 1024;
+
+/// Response content encodings, supported by the exporter
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(super) enum ContentEncoding {
+    Gzip,
+    Deflate,
+}
+
+impl ContentEncoding {
+    /// Picks the supported encoding with the highest quality from the `Accept-Encoding` header
+    /// (gzip is preferred for the same quality), `None` if the client does not accept any
+    pub(super) fn negotiate(request: &Request) -> Option<Self> {
+        let accept = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Accept-Encoding"))
+            .map(|h| h.value.as_str())?;
+        let mut result = None;
+        let mut result_q = 0.0;
+        for entry in accept.split(',') {
+            let mut parts = entry.split(';').map(str::trim);
+            let encoding = match parts.next() {
+                Some(e) if e.eq_ignore_ascii_case("gzip") || e == "*" => ContentEncoding::Gzip,
+                Some(e) if e.eq_ignore_ascii_case("deflate") => ContentEncoding::Deflate,
+                _ => continue,
+            };
+            let q = parts
+                .find_map(|p| p.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            // q=0 means "not acceptable"
+            if q <= 0.0 {
+                continue;
+            }
+            if q > result_q || (encoding == ContentEncoding::Gzip && q >= result_q) {
+                result = Some(encoding);
+                result_q = q;
+            }
+        }
+        result
+    }
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Deflate => "deflate",
+        }
+    }
+    /// Compresses the data, `None` if the data is too small to be compressed
+    pub(super) fn compress(self, data: &[u8]) -> Option<Vec<u8>> {
+        if data.len() < MIN_SIZE {
+            return None;
+        }
+        let buf = Vec::with_capacity(data.len() / 4);
+        match self {
+            ContentEncoding::Gzip => {
+                let mut encoder = GzEncoder::new(buf, Compression::default());
+                encoder.write_all(data).ok()?;
+                encoder.finish().ok()
+            }
+            // HTTP "deflate" is the zlib format
+            ContentEncoding::Deflate => {
+                let mut encoder = ZlibEncoder::new(buf, Compression::default());
+                encoder.write_all(data).ok()?;
+                encoder.finish().ok()
+            }
+        }
+    }
+}
+
+/// Compressed versions of static content, the content is compressed once per encoding
+#[cfg_attr(not(feature = "exporter-ui"), allow(dead_code))]
+pub(super) struct Cache {
+    gzip: OnceLock<Option<Vec<u8>>>,
+    deflate: OnceLock<Option<Vec<u8>>>,
+}
+
+#[cfg_attr(not(feature = "exporter-ui"), allow(dead_code))]
+impl Cache {
+    pub(super) const fn new() -> Self {
+        Self {
+            gzip: OnceLock::new(),
+            deflate: OnceLock::new(),
+        }
+    }
+    /// Compressed data (see [`ContentEncoding::compress`]), the data must be always the same
+    pub(super) fn get(&self, encoding: ContentEncoding, data: &[u8]) -> Option<&[u8]> {
+        let cell = match encoding {
+            ContentEncoding::Gzip => &self.gzip,
+            ContentEncoding::Deflate => &self.deflate,
+        };
+        cell.get_or_init(|| encoding.compress(data)).as_deref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Read, Write},
+        net::{SocketAddr, TcpStream},
+        sync::{Arc, Mutex},
+    };
+
+    use flate2::read::{GzDecoder, ZlibDecoder};
+
+    use crate::{Rack, action, exporter::Exporter};
+
+    fn get_encoded(
+        addr: SocketAddr,
+        path: &str,
+        accept_encoding: &str,
+        extra_headers: &str,
+    ) -> (String, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: {}\r\n{}\
+             Connection: close\r\n\r\n",
+            path, accept_encoding, extra_headers
+        )
+        .unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let pos = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8(response[..pos].to_vec()).unwrap();
+        let mut body = response[pos + 4..].to_vec();
+        // large bodies are chunked
+        if head.contains("Transfer-Encoding: chunked") {
+            let mut chunked = &body[..];
+            let mut data = Vec::new();
+            loop {
+                let eol = chunked.windows(2).position(|w| w == b"\r\n").unwrap();
+                let size = usize::from_str_radix(std::str::from_utf8(&chunked[..eol]).unwrap(), 16)
+                    .unwrap();
+                if size == 0 {
+                    break;
+                }
+                data.extend_from_slice(&chunked[eol + 2..eol + 2 + size]);
+                chunked = &chunked[eol + 2 + size + 2..];
+            }
+            body = data;
+        }
+        (head, body)
+    }
+
+    #[test]
+    fn test_compression() {
+        let rack = Arc::new(Mutex::new(Rack::new().with_recording_enabled()));
+        {
+            let mut rack = rack.lock().unwrap();
+            let mut processor = rack.processor();
+            let input = vec![31; 1000];
+            processor
+                .line("fan_on", &input)
+                .then(action!("temp_high", |t: &Vec<i32>| (t[0] > 30).then_some(())));
+            rack.ingress(&mut processor);
+        }
+        let exporter = Exporter::from_rack(rack.clone())
+            .with_compression()
+            .spawn("127.0.0.1:0")
+            .unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let expected = serde_json::to_string(&rack.lock().unwrap().snapshot()).unwrap();
+        let (head, body) = get_encoded(addr, "/state", "deflate;q=0.5, gzip", "");
+        assert!(head.contains("Content-Encoding: gzip"));
+        assert!(body.len() < expected.len());
+        let mut decoded = String::new();
+        GzDecoder::new(&body[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, expected);
+        let (head, body) = get_encoded(addr, "/state", "deflate", "");
+        assert!(head.contains("Content-Encoding: deflate"));
+        let mut decoded = String::new();
+        ZlibDecoder::new(&body[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, expected);
+        let (head, body) = get_encoded(addr, "/state", "br, gzip;q=0", "");
+        assert!(!head.contains("Content-Encoding"));
+        assert!(head.contains("Vary: Accept-Encoding"));
+        assert_eq!(String::from_utf8(body).unwrap(), expected);
+        // the same weak tag for all encodings, 304 responses vary as well
+        let etag = head
+            .lines()
+            .find_map(|l| l.strip_prefix("ETag: "))
+            .unwrap()
+            .to_owned();
+        assert!(etag.starts_with("W/\""));
+        let (head, body) = get_encoded(
+            addr,
+            "/state",
+            "gzip",
+            &format!("If-None-Match: {}\r\n", etag),
+        );
+        assert!(head.starts_with("HTTP/1.1 304"));
+        assert!(head.contains("Vary: Accept-Encoding"));
+        assert!(body.is_empty());
+        #[cfg(feature = "exporter-ui")]
+        for _ in 0..2 {
+            let (head, body) = get_encoded(addr, "/", "gzip", "");
+            assert!(head.contains("Content-Encoding: gzip"));
+            let mut decoded = String::new();
+            GzDecoder::new(&body[..])
+                .read_to_string(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, super::super::UI_HTML);
+        }
+        exporter.stop().unwrap();
+    }
+}
@@ -0,0 +1,253 @@
+use serde::Serialize;
+use tiny_http::{Header, Method, Request, Response};
+
+use super::{Cors, Exporter, RackAccessFn, json_response, path, query, respond};
+use crate::Rack;
+
+/// Rack status, returned by the control endpoints
+#[derive(Serialize)]
+struct Status {
+    recording: bool,
+    stats: bool,
+    lines: usize,
+    generation: u64,
+}
+
+impl Status {
+    fn of(rack: &Rack) -> Self {
+        Status {
+            recording: rack.is_recording(),
+            stats: rack.stats().is_some(),
+            lines: rack.lines().len(),
+            generation: rack.generation(),
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "1" | "on" => Some(true),
+        "false" | "0" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+enum Command {
+    Status,
+    Recording(bool),
+    Clear,
+    ResetStats,
+    Reset,
+}
+
+impl Command {
+    /// Parses the command, returns the response status code on errors
+    fn parse(request: &Request) -> Result<Self, u16> {
+        match (request.method(), path(request)) {
+            (Method::Get, "/control") => Ok(Command::Status),
+            (Method::Post, "/control/recording") => query(request)
+                .find(|(k, _)| *k == "enabled")
+                .and_then(|(_, v)| parse_bool(v))
+                .map(Command::Recording)
+                .ok_or(400),
+            (Method::Post, "/control/clear") => Ok(Command::Clear),
+            (Method::Post, "/control/reset_stats") => Ok(Command::ResetStats),
+            (Method::Post, "/control/reset") => Ok(Command::Reset),
+            (_, "/control") => Err(405),
+            (Method::Post, _) => Err(404),
+            _ => Err(405),
+        }
+    }
+    fn modifies(&self) -> bool {
+        !matches!(self, Command::Status)
+    }
+    fn execute(&self, rack: &mut Rack) {
+        match self {
+            Command::Status => {}
+            Command::Recording(enabled) => rack.set_recording(*enabled),
+            Command::Clear => rack.clear(),
+            Command::ResetStats => rack.reset_stats(),
+            Command::Reset => {
+                rack.clear();
+                rack.reset_stats();
+            }
+        }
+    }
+}
+
+/// Handles `/control` requests:
+///
+/// * `GET /control` - the rack status
+///
+/// * `POST /control/recording?enabled=<true|false>` - enable/disable recording
+///
+/// * `POST /control/clear` - clear the recorded line states
+///
+/// * `POST /control/reset_stats` - reset statistics (counters and pass/fail transition history)
+///
+/// * `POST /control/reset` - clear the line states and reset statistics
+///
+/// All the endpoints respond with the rack status.
+///
+/// To protect the rack from cross-site requests (browsers may resend cached credentials), the
+/// modifying requests must have `Content-Type: application/json` header (so browsers send CORS
+/// preflight requests first) and must not come from origins which are not allowed by the CORS
+/// configuration
+pub(super) fn handle(
+    exporter: &Exporter,
+    rack_access: &RackAccessFn,
+    request: Request,
+    cors_headers: &[Header],
+) {
+    let command = Command::parse(&request).and_then(|command| {
+        if command.modifies() {
+            exporter.check_control(&request)?;
+        }
+        Ok(command)
+    });
+    let command = match command {
+        Ok(command) => command,
+        Err(code) => {
+            respond(request, Response::empty(code), cors_headers);
+            return;
+        }
+    };
+    let mut status = None;
+    rack_access(&mut |rack| {
+        command.execute(rack);
+        status = Some(Status::of(rack));
+    });
+    respond(request, json_response(&status), cors_headers);
+}
+
+/// Checks a modifying request, returns 403 if the origin is not allowed by the CORS
+/// configuration or 415 if the request is not JSON
+pub(super) fn check(request: &Request, cors: Option<&Cors>) -> Result<(), u16> {
+    if !cors.is_none_or(|cors| cors.allows(request)) {
+        return Err(403);
+    }
+    if !is_json(request) {
+        return Err(415);
+    }
+    Ok(())
+}
+
+fn is_json(request: &Request) -> bool {
+    request.headers().iter().any(|h| {
+        h.field.equiv("Content-Type")
+            && h.value
+                .as_str()
+                .split(';')
+                .next()
+                .is_some_and(|t| t.trim().eq_ignore_ascii_case("application/json"))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::Value;
+
+    use crate::{
+        Rack, action,
+        exporter::{Auth, Cors, Exporter, test::request},
+    };
+
+    #[test]
+    fn test_control() {
+        let rack = Arc::new(Mutex::new(Rack::new().with_stats_enabled()));
+        assert!(
+            Exporter::from_rack(rack.clone())
+                .with_control()
+                .spawn("127.0.0.1:0")
+                .is_err()
+        );
+        let exporter = Exporter::from_rack(rack.clone())
+            .with_auth(Auth::new().with_bearer_token("secret"))
+            .with_control()
+            .spawn("127.0.0.1:0")
+            .unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let call_with = |method: &str, path: &str, token: &str, headers: &str| {
+            let response = request(
+                addr,
+                &format!(
+                    "{} {} HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\n\
+                     {}Content-Length: 0\r\nConnection: close\r\n\r\n",
+                    method, path, token, headers
+                ),
+            );
+            let (head, body) = response.split_once("\r\n\r\n").unwrap();
+            let status = head[9..12].parse::<u16>().unwrap();
+            (
+                status,
+                serde_json::from_str::<Value>(body).unwrap_or_default(),
+            )
+        };
+        let call = |method: &str, path: &str, token: &str| {
+            call_with(method, path, token, "Content-Type: application/json\r\n")
+        };
+        assert_eq!(
+            call("POST", "/control/recording?enabled=true", "wrong").0,
+            401
+        );
+        let (status, body) = call("POST", "/control/recording?enabled=true", "secret");
+        assert_eq!(status, 200);
+        assert_eq!(body["recording"], true);
+        assert!(rack.lock().unwrap().is_recording());
+        {
+            let mut rack = rack.lock().unwrap();
+            let mut processor = rack.processor();
+            processor
+                .line("fan_on", 31)
+                .then(action!("temp_high", |t| (t > 30).then_some(())));
+            rack.ingress(&mut processor);
+        }
+        assert_eq!(call("GET", "/control", "secret").1["lines"], 1);
+        assert_eq!(call("POST", "/control/reset", "secret").1["lines"], 0);
+        assert!(rack.lock().unwrap().stats().unwrap().lines().is_empty());
+        assert_eq!(
+            call("POST", "/control/recording?enabled=maybe", "secret").0,
+            400
+        );
+        assert_eq!(call("GET", "/control/clear", "secret").0, 405);
+        assert_eq!(call("POST", "/control/unknown", "secret").0, 404);
+        assert_eq!(call("POST", "/control", "secret").0, 405);
+        // simple (non-preflighted) cross-site requests are rejected
+        assert_eq!(call_with("POST", "/control/reset", "secret", "").0, 415);
+        assert_eq!(
+            call_with(
+                "POST",
+                "/control/reset",
+                "secret",
+                "Content-Type: text/plain\r\n"
+            )
+            .0,
+            415
+        );
+        exporter.stop().unwrap();
+        let exporter = Exporter::from_rack(rack.clone())
+            .with_auth(Auth::new().with_bearer_token("secret"))
+            .with_cors(Cors::new().with_origin("https://scada.example.com"))
+            .with_control()
+            .spawn("127.0.0.1:0")
+            .unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let call = |origin: &str| {
+            request(
+                addr,
+                &format!(
+                    "POST /control/clear HTTP/1.1\r\nHost: localhost\r\n\
+                     Authorization: Bearer secret\r\nOrigin: {}\r\n\
+                     Content-Type: application/json\r\nContent-Length: 0\r\n\
+                     Connection: close\r\n\r\n",
+                    origin
+                ),
+            )
+        };
+        assert!(call("https://evil.example.com").starts_with("HTTP/1.1 403"));
+        assert!(call("https://scada.example.com").starts_with("HTTP/1.1 200"));
+        exporter.stop().unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use tiny_http::{Header, Request};
+
+use super::header;
+
+const DEFAULT_METHODS: &[&str] = &["GET", "POST", "OPTIONS"];
+const DEFAULT_HEADERS: &[&str] = &["Accept", "Authorization", "Content-Type", "If-None-Match"];
+const EXPOSE_HEADERS: &str = "ETag";
+
+fn origin(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Origin"))
+        .map(|h| h.value.as_str())
+}
+
+/// Exporter CORS (Cross-Origin Resource Sharing) configuration. By default, requests from any
+/// origin are allowed
+#[derive(Clone, Debug)]
+pub struct Cors {
+    // None - any origin
+    origins: Option<Vec<String>>,
+    methods: Vec<String>,
+    headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self {
+            origins: None,
+            methods: DEFAULT_METHODS.iter().map(ToString::to_string).collect(),
+            headers: DEFAULT_HEADERS.iter().map(ToString::to_string).collect(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+}
+
+impl Cors {
+    /// Creates a new CORS configuration (any origin, `GET`, `POST` and `OPTIONS` methods, the
+    /// headers used by the exporter endpoints)
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Allows the origin (e.g. `https://scada.example.com`). If any origins are set, requests
+    /// from other origins are not allowed
+    pub fn with_origin(mut self, origin: impl Into<String>) -> Self {
+        self.origins
+            .get_or_insert_with(Vec::new)
+            .push(origin.into());
+        self
+    }
+    /// Sets the allowed methods
+    pub fn with_methods<I, S>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.methods = methods.into_iter().map(Into::into).collect();
+        self
+    }
+    /// Sets the allowed request headers
+    pub fn with_headers<I, S>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.headers = headers.into_iter().map(Into::into).collect();
+        self
+    }
+    /// Allows requests with credentials (e.g. basic authentication, cached by browsers). The
+    /// request origin is sent back instead of `*`
+    pub fn with_credentials(mut self) -> Self {
+        self.credentials = true;
+        self
+    }
+    /// Sets how long preflight responses can be cached by clients
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+    /// Is the request origin allowed (requests without `Origin` header are allowed)
+    pub(crate) fn allows(&self, request: &Request) -> bool {
+        match (&self.origins, origin(request)) {
+            (Some(origins), Some(origin)) => origins.iter().any(|o| o == origin),
+            _ => true,
+        }
+    }
+    /// Response headers for the request, empty if the origin is not allowed
+    pub(crate) fn response_headers(&self, request: &Request) -> Vec<Header> {
+        let origin = origin(request);
+        let allow_origin = match (&self.origins, origin) {
+            (None, Some(origin)) if self.credentials => origin,
+            (None, _) => "*",
+            (Some(origins), Some(origin)) if origins.iter().any(|o| o == origin) => origin,
+            (Some(_), _) => return Vec::new(),
+        };
+        let mut headers = vec![
+            header("Access-Control-Allow-Origin", allow_origin),
+            header("Access-Control-Expose-Headers", EXPOSE_HEADERS),
+        ];
+        if allow_origin != "*" {
+            headers.push(header("Vary", "Origin"));
+        }
+        if self.credentials {
+            headers.push(header("Access-Control-Allow-Credentials", "true"));
+        }
+        headers
+    }
+    /// Additional response headers for preflight (`OPTIONS`) requests
+    pub(crate) fn preflight_headers(&self) -> Vec<Header> {
+        let mut headers = vec![
+            header("Access-Control-Allow-Methods", &self.methods.join(", ")),
+            header("Access-Control-Allow-Headers", &self.headers.join(", ")),
+        ];
+        if let Some(max_age) = self.max_age {
+            headers.push(header(
+                "Access-Control-Max-Age",
+                &max_age.as_secs().to_string(),
+            ));
+        }
+        headers
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::Cors;
+    use crate::{
+        Rack,
+        exporter::{Auth, Exporter, test::request},
+    };
+
+    #[test]
+    fn test_cors() {
+        let rack = Arc::new(Mutex::new(Rack::new().with_recording_enabled()));
+        let exporter = Exporter::from_rack(rack)
+            .with_auth(Auth::new().with_bearer_token("secret"))
+            .with_cors(Cors::new().with_origin("https://scada.example.com"))
+            .spawn("127.0.0.1:0")
+            .unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let call = |method: &str, origin: &str| {
+            request(
+                addr,
+                &format!(
+                    "{} /state HTTP/1.1\r\nHost: localhost\r\nOrigin: {}\r\n\
+                     Access-Control-Request-Method: GET\r\n\
+                     Access-Control-Request-Headers: authorization\r\n\
+                     Connection: close\r\n\r\n",
+                    method, origin
+                ),
+            )
+        };
+        // preflight requests are not authenticated
+        let response = call("OPTIONS", "https://scada.example.com");
+        assert!(response.starts_with("HTTP/1.1 204"));
+        assert!(response.contains("Access-Control-Allow-Origin: https://scada.example.com"));
+        assert!(response.contains(
+            "Access-Control-Allow-Headers: Accept, Authorization, Content-Type, If-None-Match"
+        ));
+        let response = call("OPTIONS", "https://other.example.com");
+        assert!(response.starts_with("HTTP/1.1 204"));
+        assert!(!response.contains("Access-Control-Allow-Origin"));
+        // unauthenticated responses contain CORS headers as well, so clients can read them
+        let response = call("GET", "https://scada.example.com");
+        assert!(response.starts_with("HTTP/1.1 401"));
+        assert!(response.contains("Access-Control-Allow-Origin: https://scada.example.com"));
+        exporter.stop().unwrap();
+    }
+}
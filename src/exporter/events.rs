@@ -0,0 +1,167 @@
+use std::{
+    io::Write,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use tiny_http::{Header, Request};
+
+use super::{Exporter, SlotGuard};
+
+/// Keep-alive comments are sent if there are no events for this period, so disconnected clients
+/// are detected
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Spawns a thread which streams snapshots to the client as Server-Sent Events. The streaming
+/// slot is released when the client is disconnected
+pub(super) fn spawn(
+    exporter: Arc<Exporter>,
+    request: Request,
+    cors: Vec<Header>,
+    stop: Arc<AtomicBool>,
+    slot: SlotGuard,
+) {
+    let _ = std::thread::Builder::new()
+        .name("ll-exporter-sse".to_string())
+        .spawn(move || {
+            let _slot = slot;
+            let writer = request.into_writer();
+            let _ = stream(&exporter, writer, &cors, &stop);
+        });
+}
+
+fn stream(
+    exporter: &Exporter,
+    mut writer: impl Write,
+    cors: &[Header],
+    stop: &AtomicBool,
+) -> std::io::Result<()> {
+    writer.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\
+          Connection: close\r\n",
+    )?;
+    for h in cors {
+        write!(writer, "{}: {}\r\n", h.field, h.value)?;
+    }
+    writer.write_all(b"\r\n")?;
+    writer.flush()?;
+    let mut last_generations = None;
+    let mut last_data = Vec::new();
+    let mut last_sent = Instant::now();
+    while !stop.load(Ordering::SeqCst) {
+        let generations = exporter.generations();
+        // without a generation source, the serialized snapshots are compared
+        if generations.is_none() || generations != last_generations {
+            let data = serde_json::to_vec(&exporter.snapshot()).unwrap_or_default();
+            if generations.is_some() || data != last_data {
+                if let Some((generation, _)) = generations {
+                    writeln!(writer, "id: {}", generation)?;
+                }
+                writer.write_all(b"event: snapshot\ndata: ")?;
+                writer.write_all(&data)?;
+                writer.write_all(b"\n\n")?;
+                writer.flush()?;
+                last_sent = Instant::now();
+            }
+            last_generations = generations;
+            last_data = data;
+        }
+        if last_sent.elapsed() >= KEEP_ALIVE {
+            writer.write_all(b": keep-alive\n\n")?;
+            writer.flush()?;
+            last_sent = Instant::now();
+        }
+        std::thread::sleep(exporter.events_interval);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpStream,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use crate::{Rack, action, exporter::Exporter};
+
+    fn ingress(rack: &Mutex<Rack>, temp: i32) {
+        let mut rack = rack.lock().unwrap();
+        let mut processor = rack.processor();
+        processor
+            .line("fan_on", temp)
+            .then(action!("temp_high", |t| (t > 30).then_some(())));
+        rack.ingress(&mut processor);
+    }
+
+    #[test]
+    fn test_events() {
+        let rack = Arc::new(Mutex::new(Rack::new().with_recording_enabled()));
+        ingress(&rack, 31);
+        let exporter = Exporter::from_rack(rack.clone())
+            .with_events_interval(Duration::from_millis(10))
+            .spawn("127.0.0.1:0")
+            .unwrap();
+        let mut stream = TcpStream::connect(exporter.local_addr().unwrap()).unwrap();
+        stream
+            .write_all(b"GET /events HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut next_data = || {
+            let mut line = String::new();
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                if let Some(data) = line.strip_prefix("data: ") {
+                    return data.trim_end().to_owned();
+                }
+            }
+        };
+        assert!(next_data().contains("\"input\":31"));
+        ingress(&rack, 20);
+        assert!(next_data().contains("\"input\":20"));
+        exporter.stop().unwrap();
+    }
+
+    #[test]
+    fn test_events_limit() {
+        let rack = Arc::new(Mutex::new(Rack::new().with_recording_enabled()));
+        let exporter = Exporter::from_rack(rack.clone())
+            .with_max_streams(1)
+            .spawn("127.0.0.1:0")
+            .unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let connect = || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream
+                .write_all(b"GET /events HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut status = String::new();
+            reader.read_line(&mut status).unwrap();
+            (status, reader)
+        };
+        let (status, first) = connect();
+        assert!(status.starts_with("HTTP/1.1 200"), "{}", status);
+        let (status, _) = connect();
+        assert!(status.starts_with("HTTP/1.1 503"), "{}", status);
+        // the slot is released when the client is disconnected
+        drop(first);
+        let mut status = String::new();
+        for temp in 0..100 {
+            ingress(&rack, temp);
+            std::thread::sleep(Duration::from_millis(20));
+            status = connect().0;
+            if status.starts_with("HTTP/1.1 200") {
+                break;
+            }
+        }
+        assert!(status.starts_with("HTTP/1.1 200"), "{}", status);
+        exporter.stop().unwrap();
+    }
+}
@@ -0,0 +1,461 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use tiny_http::{Header, Request, Response};
+
+use super::{Exporter, StateEncoding, header, line_in_group, path, percent_decode, query, respond};
+use crate::LineState;
+
+/// Maximum long-polling time
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// Process instance id, included into generation-based tags, so the tags are not reused after
+/// restarts
+static INSTANCE: LazyLock<u32> = LazyLock::new(|| {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() ^ std::process::id())
+        .unwrap_or_default()
+});
+
+/// Does the name match the glob pattern (`*` - any characters, `?` - a single character)
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // the last star position in the pattern and the name position it has been matched at
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, n));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Line filter, the conditions of different kinds are combined with AND, the values of the same
+/// kind (repeated parameters) with OR
+#[derive(Default)]
+struct Filter {
+    prefixes: Vec<String>,
+    globs: Vec<String>,
+    groups: Vec<String>,
+    passed: Option<bool>,
+}
+
+impl Filter {
+    fn matches(&self, line: &LineState) -> bool {
+        let name = line.name();
+        (self.prefixes.is_empty() || self.prefixes.iter().any(|p| name.starts_with(p.as_str())))
+            && (self.globs.is_empty() || self.globs.iter().any(|g| glob_match(g, name)))
+            && (self.groups.is_empty() || self.groups.iter().any(|g| line_in_group(name, g)))
+            && self.passed.is_none_or(|passed| line.passed() == passed)
+    }
+}
+
+/// Requested resource: the whole (filtered) state or a single line
+enum Target {
+    State(Filter),
+    Line(String),
+}
+
+impl Target {
+    fn parse(request: &Request) -> Result<Self, String> {
+        if let Some(name) = path(request).strip_prefix("/state/") {
+            return Ok(Target::Line(percent_decode(name)));
+        }
+        let mut filter = Filter::default();
+        for (k, v) in query(request) {
+            let v = percent_decode(v);
+            match k {
+                "prefix" => filter.prefixes.push(v),
+                "glob" => filter.globs.push(v),
+                "group" => filter.groups.push(v),
+                "status" => {
+                    filter.passed = match v.as_str() {
+                        "passed" => Some(true),
+                        "failed" => Some(false),
+                        _ => return Err(format!("invalid status: {}", v)),
+                    };
+                }
+                _ => {}
+            }
+        }
+        Ok(Target::State(filter))
+    }
+    /// Encoded response body, `None` if the line is not found
+    fn body(&self, exporter: &Exporter, encoding: StateEncoding) -> Option<Vec<u8>> {
+        let snapshot = exporter.snapshot();
+        match self {
+            Target::State(filter) => {
+                Some(encoding.encode(&snapshot.filtered(|line| filter.matches(line))))
+            }
+            Target::Line(name) => snapshot.line_state(name).map(|line| encoding.encode(line)),
+        }
+    }
+}
+
+/// Response body of the current state
+enum Body {
+    /// Not encoded yet
+    Pending,
+    Encoded(Vec<u8>),
+    NotFound,
+}
+
+/// Entity tag of the current state, the body is encoded only if required
+struct State {
+    tag: String,
+    body: Body,
+}
+
+impl State {
+    fn current(exporter: &Exporter, target: &Target, encoding: StateEncoding) -> Self {
+        #[allow(unused_mut)]
+        let mut state = if let Some((generation, stats_generation)) = exporter.generations() {
+            // line states do not contain statistics
+            let stats_generation = match target {
+                Target::State(_) => stats_generation,
+                Target::Line(_) => 0,
+            };
+            State {
+                tag: format!(
+                    "\"{:x}-{:x}-{:x}-{}\"",
+                    *INSTANCE,
+                    generation,
+                    stats_generation,
+                    encoding.tag_suffix()
+                ),
+                body: Body::Pending,
+            }
+        } else {
+            // without a generation source, the encoded snapshot is hashed
+            let body = target.body(exporter, encoding);
+            let mut hasher = DefaultHasher::new();
+            body.hash(&mut hasher);
+            State {
+                tag: format!("\"h{:016x}\"", hasher.finish()),
+                body: body.map_or(Body::NotFound, Body::Encoded),
+            }
+        };
+        // the same tag is sent for all content encodings
+        #[cfg(feature = "exporter-compression")]
+        if exporter.compression {
+            state.tag.insert_str(0, "W/");
+        }
+        state
+    }
+}
+
+/// Does the `If-None-Match` header value match the tag
+fn matches(if_none_match: &str, tag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|t| t == "*" || t == tag || t.strip_prefix("W/") == Some(tag))
+}
+
+/// Handles `/state` and `/state/{line}` requests. Requests with `wait` parameter (seconds) are
+/// long-polled in separate threads (limited with [`Exporter::with_max_waiters`])
+pub(super) fn handle(
+    exporter: &Arc<Exporter>,
+    request: Request,
+    cors: Vec<Header>,
+    stop: &Arc<AtomicBool>,
+) {
+    let target = match Target::parse(&request) {
+        Ok(target) => target,
+        Err(e) => {
+            respond(
+                request,
+                Response::from_string(e).with_status_code(400),
+                &cors,
+            );
+            return;
+        }
+    };
+    let wait = query(&request)
+        .find(|(k, _)| *k == "wait")
+        .and_then(|(_, v)| v.parse::<f64>().ok())
+        .filter(|v| v.is_finite() && *v > 0.0)
+        .map(|v| Duration::from_secs_f64(v.min(MAX_WAIT.as_secs_f64())));
+    if let Some(wait) = wait {
+        let Some(slot) = exporter.waiters.acquire() else {
+            respond(request, Response::empty(503), &cors);
+            return;
+        };
+        let exporter = exporter.clone();
+        let stop = stop.clone();
+        let _ = std::thread::Builder::new()
+            .name("ll-exporter-poll".to_string())
+            .spawn(move || {
+                let _slot = slot;
+                respond_state(&exporter, request, &target, &cors, Some(wait), &stop);
+            });
+    } else {
+        respond_state(exporter, request, &target, &cors, None, stop);
+    }
+}
+
+fn respond_state(
+    exporter: &Exporter,
+    request: Request,
+    target: &Target,
+    cors: &[Header],
+    wait: Option<Duration>,
+    stop: &AtomicBool,
+) {
+    let encoding = StateEncoding::negotiate(&request);
+    let if_none_match = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("If-None-Match"))
+        .map(|h| h.value.as_str().to_owned());
+    let mut state = State::current(exporter, target, encoding);
+    if let Some(wait) = wait {
+        // waits until the state differs from the client one (or from the current one)
+        let known = if_none_match.clone().unwrap_or_else(|| state.tag.clone());
+        let deadline = Instant::now() + wait;
+        while matches(&known, &state.tag) && !stop.load(Ordering::SeqCst) {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            std::thread::sleep(exporter.events_interval.min(deadline - now));
+            state = State::current(exporter, target, encoding);
+        }
+    }
+    let response = if if_none_match.is_some_and(|v| matches(&v, &state.tag)) {
+        let response = Response::from_data(Vec::new()).with_status_code(304);
+        #[cfg(feature = "exporter-compression")]
+        let response = if exporter.compression {
+            response.with_header(header("Vary", "Accept-Encoding"))
+        } else {
+            response
+        };
+        response
+    } else if let Some(body) = match state.body {
+        Body::Pending => target.body(exporter, encoding),
+        Body::Encoded(body) => Some(body),
+        Body::NotFound => None,
+    } {
+        #[cfg(feature = "exporter-compression")]
+        let response = exporter.body_response(&request, body.into(), None);
+        #[cfg(not(feature = "exporter-compression"))]
+        let response = Exporter::body_response(body.into());
+        response.with_header(header("Content-Type", encoding.content_type()))
+    } else {
+        Response::from_data(Vec::new()).with_status_code(404)
+    };
+    let response = response
+        .with_header(header("ETag", &state.tag))
+        .with_header(header("Cache-Control", "no-cache"))
+        .with_header(header("Vary", "Accept"));
+    respond(request, response, cors);
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use crate::{
+        Rack, action,
+        exporter::{
+            Exporter,
+            test::{get, request},
+        },
+    };
+
+    fn ingress(rack: &Mutex<Rack>, temp: i32) {
+        let mut rack = rack.lock().unwrap();
+        let mut processor = rack.processor();
+        processor
+            .line("fan_on", temp)
+            .then(action!("temp_high", |t| (t > 30).then_some(())));
+        rack.ingress(&mut processor);
+    }
+
+    fn etag(response: &str) -> &str {
+        response
+            .lines()
+            .find_map(|l| l.strip_prefix("ETag: "))
+            .unwrap()
+    }
+
+    fn get_if_none_match(addr: SocketAddr, path: &str, tag: &str) -> String {
+        request(
+            addr,
+            &format!(
+                "GET {} HTTP/1.1\r\nHost: localhost\r\nIf-None-Match: {}\r\n\
+                 Connection: close\r\n\r\n",
+                path, tag
+            ),
+        )
+    }
+
+    #[test]
+    fn test_conditional_state() {
+        let rack = Arc::new(Mutex::new(Rack::new().with_recording_enabled()));
+        ingress(&rack, 31);
+        let exporter = Exporter::from_rack(rack.clone())
+            .with_events_interval(Duration::from_millis(10))
+            .spawn("127.0.0.1:0")
+            .unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let response = get(addr, "/state");
+        let tag = etag(&response).to_owned();
+        assert!(get_if_none_match(addr, "/state", &tag).starts_with("HTTP/1.1 304"));
+        // no changes during the timeout
+        assert!(get_if_none_match(addr, "/state?wait=0.1", &tag).starts_with("HTTP/1.1 304"));
+        let poll = std::thread::spawn({
+            let tag = tag.clone();
+            move || get_if_none_match(addr, "/state?wait=10", &tag)
+        });
+        std::thread::sleep(Duration::from_millis(100));
+        ingress(&rack, 20);
+        let response = poll.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("\"input\":20"));
+        assert_ne!(etag(&response), tag);
+        exporter.stop().unwrap();
+    }
+
+    #[test]
+    fn test_conditional_state_limit() {
+        let rack = Arc::new(Mutex::new(Rack::new().with_recording_enabled()));
+        ingress(&rack, 31);
+        let exporter = Exporter::from_rack(rack.clone())
+            .with_events_interval(Duration::from_millis(10))
+            .with_max_waiters(1)
+            .spawn("127.0.0.1:0")
+            .unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let tag = etag(&get(addr, "/state")).to_owned();
+        let poll = std::thread::spawn({
+            let tag = tag.clone();
+            move || get_if_none_match(addr, "/state?wait=10", &tag)
+        });
+        std::thread::sleep(Duration::from_millis(100));
+        let response = get_if_none_match(addr, "/state?wait=10", &tag);
+        assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+        // requests without waiting are not limited
+        assert!(get_if_none_match(addr, "/state", &tag).starts_with("HTTP/1.1 304"));
+        ingress(&rack, 20);
+        assert!(poll.join().unwrap().starts_with("HTTP/1.1 200"));
+        exporter.stop().unwrap();
+    }
+
+    #[test]
+    fn test_conditional_state_stats() {
+        let rack = Arc::new(Mutex::new(
+            Rack::new().with_recording_enabled().with_stats_enabled(),
+        ));
+        ingress(&rack, 31);
+        let exporter = Exporter::from_rack(rack.clone())
+            .spawn("127.0.0.1:0")
+            .unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let tag = etag(&get(addr, "/state")).to_owned();
+        let line_tag = etag(&get(addr, "/state/fan_on")).to_owned();
+        // the line state is the same, the statistics are changed
+        ingress(&rack, 31);
+        let response = get_if_none_match(addr, "/state", &tag);
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("\"executions\":2"));
+        assert_ne!(etag(&response), tag);
+        assert!(get_if_none_match(addr, "/state/fan_on", &line_tag).starts_with("HTTP/1.1 304"));
+        exporter.stop().unwrap();
+    }
+
+    #[test]
+    fn test_glob() {
+        assert!(super::glob_match("boiler/*", "boiler/fan_on"));
+        assert!(super::glob_match("*_on", "boiler/fan_on"));
+        assert!(super::glob_match("b?iler/*on", "boiler/fan_on"));
+        assert!(super::glob_match("*a*a*", "banana"));
+        assert!(!super::glob_match("*_off", "boiler/fan_on"));
+        assert!(!super::glob_match("boiler", "boiler/fan_on"));
+    }
+
+    #[test]
+    fn test_filtered_state() {
+        let rack = Arc::new(Mutex::new(Rack::new().with_recording_enabled()));
+        {
+            let mut rack = rack.lock().unwrap();
+            let mut processor = rack.processor();
+            processor
+                .line("boiler/fan on", 31)
+                .then(action!("temp_high", |t| (t > 30).then_some(())));
+            processor
+                .line("boiler/pump_on", 31)
+                .then(action!("temp_high", |t| (t > 50).then_some(())));
+            processor
+                .line("heater_on", 31)
+                .then(action!("temp_low", |t| (t < 10).then_some(())));
+            rack.ingress(&mut processor);
+        }
+        let exporter = Exporter::from_rack(rack).spawn("127.0.0.1:0").unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let lines = |path: &str| {
+            let response = get(addr, path);
+            let body = response.split_once("\r\n\r\n").unwrap().1;
+            let snapshot: crate::Snapshot = serde_json::from_str(body).unwrap();
+            snapshot.lines().keys().cloned().collect::<Vec<_>>()
+        };
+        assert_eq!(
+            lines("/state?group=boiler"),
+            ["boiler/fan on", "boiler/pump_on"]
+        );
+        assert_eq!(lines("/state?prefix=heat"), ["heater_on"]);
+        assert_eq!(lines("/state?glob=*_on"), ["boiler/pump_on", "heater_on"]);
+        assert_eq!(lines("/state?status=passed"), ["boiler/fan on"]);
+        assert_eq!(
+            lines("/state?group=boiler&status=failed"),
+            ["boiler/pump_on"]
+        );
+        assert!(get(addr, "/state?status=unknown").starts_with("HTTP/1.1 400"));
+        let response = get(addr, "/state/boiler/fan%20on");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("\"name\":\"boiler/fan on\""));
+        assert!(get(addr, "/state/boiler").starts_with("HTTP/1.1 404"));
+        exporter.stop().unwrap();
+    }
+
+    #[test]
+    fn test_conditional_state_hashed() {
+        let rack = Arc::new(Mutex::new(Rack::new().with_recording_enabled()));
+        ingress(&rack, 31);
+        let exporter = Exporter::new({
+            let rack = rack.clone();
+            move || rack.lock().unwrap().snapshot()
+        })
+        .spawn("127.0.0.1:0")
+        .unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let tag = etag(&get(addr, "/state")).to_owned();
+        assert!(get_if_none_match(addr, "/state", &tag).starts_with("HTTP/1.1 304"));
+        ingress(&rack, 20);
+        assert!(get_if_none_match(addr, "/state", &tag).starts_with("HTTP/1.1 200"));
+        exporter.stop().unwrap();
+    }
+}
@@ -0,0 +1,339 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Read, Write},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tiny_http::{Request, Response};
+use tungstenite::{Message, WebSocket, protocol::Role};
+
+use super::{Exporter, SlotGuard, header, line_in_group};
+use crate::{LineState, Snapshot};
+
+/// Clients which do not answer pings for this period are disconnected
+const PONG_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Client messages
+#[derive(Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case", deny_unknown_fields)]
+enum ClientMessage {
+    Subscribe {
+        #[serde(default)]
+        lines: Vec<String>,
+        #[serde(default)]
+        groups: Vec<String>,
+    },
+    Unsubscribe {
+        #[serde(default)]
+        lines: Vec<String>,
+        #[serde(default)]
+        groups: Vec<String>,
+    },
+}
+
+/// Server messages
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum ServerMessage<'a> {
+    /// Changed states of the subscribed lines
+    Update {
+        snapshot: &'a Snapshot,
+    },
+    /// Subscribed lines, removed from the rack
+    Remove {
+        lines: Vec<String>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Default)]
+struct Subscription {
+    lines: BTreeSet<String>,
+    groups: BTreeSet<String>,
+}
+
+impl Subscription {
+    fn matches(&self, name: &str) -> bool {
+        self.lines.contains(name) || self.groups.iter().any(|g| line_in_group(name, g))
+    }
+    fn apply(&mut self, message: ClientMessage) {
+        match message {
+            ClientMessage::Subscribe { lines, groups } => {
+                self.lines.extend(lines);
+                self.groups.extend(groups);
+            }
+            ClientMessage::Unsubscribe { lines, groups } => {
+                for line in lines {
+                    self.lines.remove(&line);
+                }
+                for group in groups {
+                    self.groups.remove(&group);
+                }
+            }
+        }
+    }
+}
+
+/// Is the request a WebSocket upgrade one
+pub(super) fn is_upgrade(request: &Request) -> bool {
+    request
+        .headers()
+        .iter()
+        .any(|h| h.field.equiv("Upgrade") && h.value.as_str().eq_ignore_ascii_case("websocket"))
+}
+
+/// Completes the WebSocket handshake and spawns a thread which serves the client. The streaming
+/// slot is released when the client is disconnected
+pub(super) fn spawn(
+    exporter: Arc<Exporter>,
+    request: Request,
+    stop: Arc<AtomicBool>,
+    slot: SlotGuard,
+) {
+    let Some(key) = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+        .map(|h| tungstenite::handshake::derive_accept_key(h.value.as_str().trim().as_bytes()))
+    else {
+        let _ = request.respond(Response::empty(400));
+        return;
+    };
+    let _ = std::thread::Builder::new()
+        .name("ll-exporter-ws".to_string())
+        .spawn(move || {
+            let _slot = slot;
+            let response = Response::empty(101).with_header(header("Sec-WebSocket-Accept", &key));
+            let stream = request.upgrade("websocket", response);
+            let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+            let _ = serve(&exporter, &mut socket, &stop);
+            let _ = socket.close(None);
+            let _ = socket.flush();
+        });
+}
+
+fn send<S: Read + Write>(
+    socket: &mut WebSocket<S>,
+    message: &ServerMessage,
+) -> tungstenite::Result<()> {
+    socket.send(Message::text(
+        serde_json::to_string(message).unwrap_or_default(),
+    ))
+}
+
+/// Is the error caused by the connection read/write timeout
+fn is_timeout(error: &tungstenite::Error) -> bool {
+    matches!(error, tungstenite::Error::Io(e)
+        if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut))
+}
+
+/// The client is pinged every interval and the messages are read until the pong is received. The
+/// reads are interrupted by the connection timeout, so the stop flag and unresponsive clients are
+/// checked between them
+fn serve<S: Read + Write>(
+    exporter: &Exporter,
+    socket: &mut WebSocket<S>,
+    stop: &AtomicBool,
+) -> tungstenite::Result<()> {
+    let mut subscription = Subscription::default();
+    let mut sent: BTreeMap<String, LineState> = BTreeMap::new();
+    let mut last_generation = None;
+    let mut resync = false;
+    while !stop.load(Ordering::SeqCst) {
+        let generation = exporter.generation.as_ref().map(|g| g());
+        if resync || generation.is_none() || generation != last_generation {
+            let snapshot = exporter.snapshot();
+            sent.retain(|name, _| subscription.matches(name));
+            let removed: Vec<String> = sent
+                .keys()
+                .filter(|name| !snapshot.lines().contains_key(name.as_str()))
+                .cloned()
+                .collect();
+            if !removed.is_empty() {
+                for name in &removed {
+                    sent.remove(name);
+                }
+                send(socket, &ServerMessage::Remove { lines: removed })?;
+            }
+            let changed: BTreeMap<Cow<'static, str>, LineState> = snapshot
+                .lines()
+                .iter()
+                .filter(|(name, line)| {
+                    subscription.matches(name) && sent.get(name.as_ref()) != Some(*line)
+                })
+                .map(|(name, line)| (name.clone(), line.clone()))
+                .collect();
+            if !changed.is_empty() {
+                for (name, line) in &changed {
+                    sent.insert(name.to_string(), line.clone());
+                }
+                let snapshot = Snapshot::new(changed, None);
+                send(
+                    socket,
+                    &ServerMessage::Update {
+                        snapshot: &snapshot,
+                    },
+                )?;
+            }
+            last_generation = generation;
+            resync = false;
+        }
+        socket.send(Message::Ping(<_>::default()))?;
+        let pinged = Instant::now();
+        loop {
+            let message = match socket.read() {
+                Ok(message) => message,
+                Err(e) if is_timeout(&e) => {
+                    if stop.load(Ordering::SeqCst) || pinged.elapsed() >= PONG_TIMEOUT {
+                        return Ok(());
+                    }
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            match message {
+                Message::Text(text) => match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(message) => {
+                        subscription.apply(message);
+                        resync = true;
+                    }
+                    Err(e) => send(
+                        socket,
+                        &ServerMessage::Error {
+                            message: e.to_string(),
+                        },
+                    )?,
+                },
+                Message::Pong(_) => break,
+                Message::Close(_) => return Ok(()),
+                _ => {}
+            }
+        }
+        if !resync {
+            std::thread::sleep(exporter.events_interval);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        net::TcpStream,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use serde_json::Value;
+    use tungstenite::{Message, WebSocket};
+
+    use crate::{Rack, action, exporter::Exporter};
+
+    fn ingress(rack: &Mutex<Rack>, temp: i32, pump_temp: i32) {
+        let mut rack = rack.lock().unwrap();
+        let mut processor = rack.processor();
+        processor
+            .line("boiler/fan_on", temp)
+            .then(action!("temp_high", |t| (t > 30).then_some(())));
+        processor
+            .line("boiler/pump_on", pump_temp)
+            .then(action!("temp_high", |t| (t > 50).then_some(())));
+        processor
+            .line("heater_on", temp)
+            .then(action!("temp_low", |t| (t < 10).then_some(())));
+        rack.ingress(&mut processor);
+    }
+
+    fn next_message(socket: &mut WebSocket<TcpStream>) -> Value {
+        loop {
+            if let Message::Text(text) = socket.read().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    fn next_lines(socket: &mut WebSocket<TcpStream>) -> Vec<String> {
+        let message = next_message(socket);
+        assert_eq!(message["event"], "update");
+        message["snapshot"]["lines"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn test_ws() {
+        let rack = Arc::new(Mutex::new(Rack::new().with_recording_enabled()));
+        ingress(&rack, 31, 31);
+        let exporter = Exporter::from_rack(rack.clone())
+            .with_events_interval(Duration::from_millis(10))
+            .spawn("127.0.0.1:0")
+            .unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let (mut socket, _) = tungstenite::client(
+            format!("ws://{}/ws", addr),
+            TcpStream::connect(addr).unwrap(),
+        )
+        .unwrap();
+        socket
+            .send(Message::text(
+                r#"{"method":"subscribe","groups":["boiler"]}"#,
+            ))
+            .unwrap();
+        assert_eq!(next_lines(&mut socket), ["boiler/fan_on", "boiler/pump_on"]);
+        // only the changed lines are sent
+        ingress(&rack, 31, 60);
+        assert_eq!(next_lines(&mut socket), ["boiler/pump_on"]);
+        socket
+            .send(Message::text(
+                r#"{"method":"subscribe","lines":["heater_on"]}"#,
+            ))
+            .unwrap();
+        assert_eq!(next_lines(&mut socket), ["heater_on"]);
+        // removed lines are reported
+        rack.lock().unwrap().clear();
+        let message = next_message(&mut socket);
+        assert_eq!(message["event"], "remove");
+        assert_eq!(
+            message["lines"],
+            serde_json::json!(["boiler/fan_on", "boiler/pump_on", "heater_on"])
+        );
+        socket.close(None).unwrap();
+        exporter.stop().unwrap();
+    }
+
+    #[test]
+    fn test_ws_limit() {
+        let rack = Arc::new(Mutex::new(Rack::new().with_recording_enabled()));
+        let exporter = Exporter::from_rack(rack.clone())
+            .with_max_streams(1)
+            .spawn("127.0.0.1:0")
+            .unwrap();
+        let addr = exporter.local_addr().unwrap();
+        let connect = || {
+            tungstenite::client(
+                format!("ws://{}/ws", addr),
+                TcpStream::connect(addr).unwrap(),
+            )
+        };
+        let (_socket, _) = connect().unwrap();
+        match connect() {
+            Err(tungstenite::HandshakeError::Failure(tungstenite::Error::Http(response))) => {
+                assert_eq!(response.status(), 503);
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("the client limit is not applied"),
+        }
+        exporter.stop().unwrap();
+    }
+}
@@ -15,9 +15,53 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "recording")]
 mod recording;
 #[cfg(feature = "recording")]
-pub use recording::{InputKind, LineState, Snapshot, SnapshotFormatter, StepState, StepStateInfo};
+use recording::RecordedInput;
 #[cfg(feature = "recording")]
-use serde_json::Value;
+pub use recording::{
+    InputKind, LineState, SNAPSHOT_FORMAT_VERSION, Snapshot, SnapshotFormatter, StepState,
+    StepStateInfo,
+};
+#[cfg(feature = "recording")]
+mod stats;
+#[cfg(feature = "recording")]
+pub use stats::{Counters, LineStats, RackStats, StepStats};
+/// Built-in exporter (HTTP server)
+#[cfg(feature = "exporter")]
+pub mod exporter;
+/// Prometheus metrics of recorded line states
+#[cfg(feature = "recording")]
+pub mod metrics;
+/// Logic regression testing with recorded line inputs
+#[cfg(feature = "recording")]
+pub mod regression;
+/// Renderers of recorded line states into diagrams and documents
+#[cfg(feature = "recording")]
+pub mod render;
+/// Replay of recorded traces
+#[cfg(feature = "recording")]
+pub mod replay;
+/// Golden-snapshot testing helpers
+#[cfg(feature = "recording")]
+pub mod testing;
+/// Trace (recorded history) writing and reading
+#[cfg(feature = "recording")]
+pub mod trace;
+/// VCD (Value Change Dump) export of recorded history
+#[cfg(feature = "recording")]
+pub mod vcd;
+
+#[cfg(feature = "locking-rt-safe")]
+use rtsc::pi::Mutex;
+
+#[cfg(all(feature = "locking-rt", not(feature = "locking-rt-safe"),))]
+use parking_lot_rt::Mutex;
+
+#[cfg(all(
+    feature = "locking-default",
+    not(feature = "locking-rt-safe"),
+    not(feature = "locking-rt")
+))]
+use parking_lot::Mutex;
 
 /// The process global state
 pub mod global {
@@ -28,20 +72,7 @@ pub mod global {
     use std::net::{IpAddr, ToSocketAddrs};
     use std::sync::LazyLock;
 
-    use super::{Processor, Rack};
-
-    #[cfg(feature = "locking-rt-safe")]
-    use rtsc::pi::Mutex;
-
-    #[cfg(all(feature = "locking-rt", not(feature = "locking-rt-safe"),))]
-    use parking_lot_rt::Mutex;
-
-    #[cfg(all(
-        feature = "locking-default",
-        not(feature = "locking-rt-safe"),
-        not(feature = "locking-rt")
-    ))]
-    use parking_lot::Mutex;
+    use super::{Mutex, Processor, Rack};
 
     static GLOBAL_LADDER: LazyLock<Mutex<Rack>> = LazyLock::new(|| Mutex::new(Rack::new()));
 
@@ -73,6 +104,30 @@ pub mod global {
         GLOBAL_LADDER.lock().is_recording()
     }
 
+    /// Enables/disables pass/fail statistics for the global rack state
+    #[cfg(feature = "recording")]
+    pub fn set_stats(enabled: bool) {
+        GLOBAL_LADDER.lock().set_stats(enabled);
+    }
+
+    /// Resets pass/fail statistics of the global rack state
+    #[cfg(feature = "recording")]
+    pub fn reset_stats() {
+        GLOBAL_LADDER.lock().reset_stats();
+    }
+
+    /// Clears the recorded line states of the global rack state
+    #[cfg(feature = "recording")]
+    pub fn clear() {
+        GLOBAL_LADDER.lock().clear();
+    }
+
+    /// Attaches/detaches a trace writer to the global rack state
+    #[cfg(feature = "recording")]
+    pub fn set_tracer(tracer: Option<super::trace::Tracer>) {
+        GLOBAL_LADDER.lock().set_tracer(tracer);
+    }
+
     /// Creates a snapshot of the global state
     #[cfg(feature = "recording")]
     pub fn snapshot() -> super::Snapshot {
@@ -88,6 +143,18 @@ pub mod global {
         GLOBAL_LADDER.lock().snapshot_filtered(predicate)
     }
 
+    /// Generation of the global state, see [`Rack::generation`]
+    #[cfg(feature = "recording")]
+    pub fn generation() -> u64 {
+        GLOBAL_LADDER.lock().generation()
+    }
+
+    /// Statistics generation of the global state, see [`Rack::stats_generation`]
+    #[cfg(feature = "recording")]
+    pub fn stats_generation() -> u64 {
+        GLOBAL_LADDER.lock().stats_generation()
+    }
+
     /// Creates a new processor for the global state
     pub fn processor() -> Processor {
         GLOBAL_LADDER.lock().processor()
@@ -109,85 +176,32 @@ pub mod global {
         install_exporter_on((IpAddr::from([0, 0, 0, 0]), DEFAULT_PORT))
     }
 
-    /// Installs the exporter (HTTP server) on the specified address
-    ///
-    /// # Panics
-    ///
-    /// Should not panic
+    /// Installs the exporter (HTTP server) on the specified address. The exporter thread is
+    /// detached, use [`exporter`] to get a stoppable one
     #[cfg(feature = "exporter")]
     pub fn install_exporter_on<A: ToSocketAddrs>(
         addr: A,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let server = tiny_http::Server::http(addr).map_err(|e| e.to_string())?;
-        std::thread::Builder::new()
-            .name("ll-exporter".to_string())
-            .spawn(move || {
-                loop {
-                    let Ok(request) = server.recv() else {
-                        std::thread::sleep(std::time::Duration::from_millis(100));
-                        continue;
-                    };
-                    if request.method() != &tiny_http::Method::Get {
-                        let response = tiny_http::Response::empty(406);
-                        let _ = request.respond(response);
-                        continue;
-                    }
-                    if request.url() == "/state" {
-                        let mut snapshot = snapshot();
-                        if let Some(formatter) = SNAPSHOT_FORMATTER.get() {
-                            snapshot = formatter.format(snapshot);
-                        }
-                        let json = serde_json::to_string(&snapshot).unwrap_or_default();
-                        let mut response = tiny_http::Response::from_string(json);
-                        response.add_header(
-                            tiny_http::Header::from_bytes(
-                                &b"Content-Type"[..],
-                                &b"application/json"[..],
-                            )
-                            .unwrap(),
-                        );
-                        response.add_header(
-                            tiny_http::Header::from_bytes(
-                                &b"Access-Control-Allow-Origin"[..],
-                                &b"*"[..],
-                            )
-                            .unwrap(),
-                        );
-                        response.add_header(
-                            tiny_http::Header::from_bytes(
-                                &b"Access-Control-Allow-Methods"[..],
-                                &b"GET, OPTIONS"[..],
-                            )
-                            .unwrap(),
-                        );
-                        response.add_header(
-                            tiny_http::Header::from_bytes(
-                                &b"Access-Control-Allow-Headers"[..],
-                                &b"Content-Type"[..],
-                            )
-                            .unwrap(),
-                        );
-                        let _ = request.respond(response);
-                        continue;
-                    }
-                    #[cfg(feature = "exporter-ui")]
-                    if request.url() == "/" {
-                        let mut response = tiny_http::Response::from_string(include_str!(
-                            "../ll-default-view/dist/index.html"
-                        ));
-                        response.add_header(
-                            tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/html"[..])
-                                .unwrap(),
-                        );
-                        let _ = request.respond(response);
-                        continue;
-                    }
-                    let response = tiny_http::Response::empty(404);
-                    let _ = request.respond(response);
-                }
-            })?;
+        exporter().spawn(addr)?;
         Ok(())
     }
+
+    /// Creates an exporter builder for the global state (the global snapshot formatter is
+    /// applied)
+    #[cfg(feature = "exporter")]
+    pub fn exporter() -> super::exporter::Exporter {
+        super::exporter::Exporter::new(|| {
+            let snapshot = snapshot();
+            if let Some(formatter) = SNAPSHOT_FORMATTER.get() {
+                formatter.format(snapshot)
+            } else {
+                snapshot
+            }
+        })
+        .with_generation(generation)
+        .with_stats_generation(stats_generation)
+        .with_rack_access(|f| f(&mut GLOBAL_LADDER.lock()))
+    }
 }
 
 /// Logical step in the line
@@ -254,6 +268,20 @@ where
         processor.result.get_mut(line_name)
     }
 
+    /// Records the line input with the first step of the line (the input is serializable here,
+    /// so [`Processor::line`] does not require it)
+    #[cfg(feature = "recording")]
+    fn record_line_input(&mut self) {
+        if self.line_state_mut().is_none_or(|l| l.has_input()) {
+            return;
+        }
+        if let Some(input) = self.input.as_ref().map(RecordedInput::pack)
+            && let Some(l) = self.line_state_mut()
+        {
+            l.set_input(input);
+        }
+    }
+
     /// Passes the step in case if any of the actions returns `Some`
     #[allow(clippy::missing_panics_doc)]
     pub fn then_any<OUTPUT, A, A2, F, F2>(mut self, action1: A, action2: A2) -> Step<'p, OUTPUT>
@@ -265,13 +293,17 @@ where
         F2: FnOnce(INPUT) -> Option<OUTPUT>,
         INPUT: Clone,
     {
+        #[cfg(feature = "recording")]
+        if self.processor_is_recording() {
+            self.record_line_input();
+        }
         #[allow(unused_mut)]
         let mut action1 = action1.into();
         #[cfg(feature = "recording")]
         let input_kind1 = action1.input_kind();
         #[cfg(feature = "recording")]
         let recorded_input1 = if self.processor_is_recording() {
-            action1.take_recorded_input_serialized(self.input.as_ref())
+            action1.take_recorded_input(self.input.as_ref())
         } else {
             <_>::default()
         };
@@ -281,7 +313,7 @@ where
         let input_kind2 = action2.input_kind();
         #[cfg(feature = "recording")]
         let recorded_input2 = if self.processor_is_recording() {
-            action2.take_recorded_input_serialized(self.input.as_ref())
+            action2.take_recorded_input(self.input.as_ref())
         } else {
             <_>::default()
         };
@@ -310,7 +342,7 @@ where
         if let Some(output) = (action1.f)(action_input.clone()) {
             next_input = Some(output);
             #[cfg(feature = "recording")]
-            step_states.push(StepStateInfo::new_with_serialized_input(
+            step_states.push(StepStateInfo::new_with_recorded_input(
                 action1.name,
                 recorded_input1,
                 input_kind1,
@@ -318,7 +350,7 @@ where
             ));
         } else {
             #[cfg(feature = "recording")]
-            step_states.push(StepStateInfo::new_with_serialized_input(
+            step_states.push(StepStateInfo::new_with_recorded_input(
                 action1.name,
                 recorded_input1,
                 input_kind1,
@@ -330,7 +362,7 @@ where
                 next_input = Some(output);
             }
             #[cfg(feature = "recording")]
-            step_states.push(StepStateInfo::new_with_serialized_input(
+            step_states.push(StepStateInfo::new_with_recorded_input(
                 action2.name,
                 recorded_input2,
                 input_kind2,
@@ -338,7 +370,7 @@ where
             ));
         } else {
             #[cfg(feature = "recording")]
-            step_states.push(StepStateInfo::new_with_serialized_input(
+            step_states.push(StepStateInfo::new_with_recorded_input(
                 action2.name,
                 recorded_input2,
                 input_kind2,
@@ -364,6 +396,10 @@ where
         A: Into<Action<'p, F, INPUT, OUTPUT>>,
         F: FnOnce(INPUT) -> Option<OUTPUT>,
     {
+        #[cfg(feature = "recording")]
+        if self.processor_is_recording() {
+            self.record_line_input();
+        }
         #[allow(unused_mut)]
         let mut action = action.into();
         #[cfg(feature = "recording")]
@@ -378,7 +414,7 @@ where
         }
         if !self.active || self.input.is_none() {
             #[cfg(feature = "recording")]
-            record_processed!(action.name, false, Value::Null);
+            record_processed!(action.name, false, RecordedInput::null());
             return Step {
                 input: None,
                 active: false,
@@ -388,7 +424,7 @@ where
         }
         #[cfg(feature = "recording")]
         let recorded_input = if self.processor_is_recording() {
-            action.take_recorded_input_serialized(self.input.as_ref())
+            action.take_recorded_input(self.input.as_ref())
         } else {
             <_>::default()
         };
@@ -492,14 +528,14 @@ where
         }
     }
     #[cfg(feature = "recording")]
-    fn take_recorded_input_serialized(&mut self, fallback: Option<&INPUT>) -> Value
+    fn take_recorded_input(&mut self, fallback: Option<&INPUT>) -> RecordedInput
     where
         INPUT: StepInput,
     {
         if let Some(i) = self.recorded_input.take() {
-            serde_json::to_value(i).unwrap_or_default()
+            RecordedInput::pack(i)
         } else {
-            serde_json::to_value(fallback).unwrap_or_default()
+            RecordedInput::pack(&fallback)
         }
     }
 }
@@ -514,6 +550,22 @@ pub struct Rack {
     #[serde(skip)]
     #[cfg(feature = "recording")]
     recording: Arc<atomic::AtomicBool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg(feature = "recording")]
+    stats: Option<RackStats>,
+    #[serde(skip)]
+    #[cfg(feature = "recording")]
+    tracer: Option<trace::Tracer>,
+    // the tracer has not got the full line states yet (attached, cleared or a message dropped)
+    #[serde(skip)]
+    #[cfg(feature = "recording")]
+    trace_full: bool,
+    #[serde(skip)]
+    #[cfg(feature = "recording")]
+    generation: u64,
+    #[serde(skip)]
+    #[cfg(feature = "recording")]
+    stats_generation: u64,
 }
 
 impl Rack {
@@ -525,7 +577,39 @@ impl Rack {
     #[allow(unused_variables)]
     pub fn ingress(&mut self, processor: &mut Processor) {
         #[cfg(feature = "recording")]
-        self.lines.extend(mem::take(&mut processor.result));
+        {
+            if processor.result.is_empty() {
+                return;
+            }
+            if let Some(stats) = self.stats.as_mut() {
+                stats.record(&processor.result);
+                self.stats_generation = self.stats_generation.wrapping_add(1);
+            }
+            let changed = processor
+                .result
+                .iter()
+                .any(|(name, line)| self.lines.get(name) != Some(line));
+            if changed {
+                self.generation = self.generation.wrapping_add(1);
+            }
+            let result = mem::take(&mut processor.result);
+            if let Some(ref tracer) = self.tracer {
+                // only the ingressed lines are sent, the writer merges them into its own state
+                self.lines.extend(
+                    result
+                        .iter()
+                        .map(|(name, line)| (name.clone(), line.clone())),
+                );
+                let (lines, full) = if self.trace_full {
+                    (self.lines.clone(), true)
+                } else {
+                    (result, false)
+                };
+                self.trace_full = !tracer.send_lines(stats::now(), lines, full, changed);
+            } else {
+                self.lines.extend(result);
+            }
+        }
         #[cfg(not(feature = "recording"))]
         processor.reset();
     }
@@ -539,12 +623,23 @@ impl Rack {
     pub fn lines(&self) -> &BTreeMap<Cow<'static, str>, LineState> {
         &self.lines
     }
+    /// Rack generation: a counter which is increased every time the ingress changes the
+    /// recorded line states (statistics changes are not counted). Can be used to detect changes
+    /// without comparing snapshots
+    #[cfg(feature = "recording")]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+    /// Statistics generation: a counter which is increased every time the statistics are changed
+    /// (updated on ingress, reset, enabled or disabled)
+    #[cfg(feature = "recording")]
+    pub fn stats_generation(&self) -> u64 {
+        self.stats_generation
+    }
     /// Creates a snapshot of the current state of the lines
     #[cfg(feature = "recording")]
     pub fn snapshot(&self) -> Snapshot {
-        Snapshot {
-            lines: self.lines.clone(),
-        }
+        Snapshot::new(self.lines.clone(), self.stats.clone())
     }
     /// Creates a filtered snapshot of the current state of the lines
     #[cfg(feature = "recording")]
@@ -558,7 +653,8 @@ impl Rack {
             .filter(|(_, line)| predicate(line))
             .map(|(name, line)| (name.clone(), line.clone()))
             .collect();
-        Snapshot { lines }
+        let stats = self.stats.as_ref().map(|stats| stats.filtered(&lines));
+        Snapshot::new(lines, stats)
     }
     /// Creates a new processor
     pub fn processor(&self) -> Processor {
@@ -588,6 +684,66 @@ impl Rack {
     pub fn is_recording(&self) -> bool {
         self.recording.load(atomic::Ordering::SeqCst)
     }
+
+    /// Enables pass/fail statistics for the state. The statistics are updated on ingress for the
+    /// recorded lines only
+    #[cfg(feature = "recording")]
+    pub fn with_stats_enabled(mut self) -> Self {
+        self.set_stats(true);
+        self
+    }
+
+    /// Enables/disables pass/fail statistics for the state. When disabled, the accumulated
+    /// statistics are dropped
+    #[cfg(feature = "recording")]
+    pub fn set_stats(&mut self, enabled: bool) {
+        if enabled == self.stats.is_some() {
+            return;
+        }
+        self.stats = enabled.then(RackStats::default);
+        self.stats_generation = self.stats_generation.wrapping_add(1);
+    }
+
+    /// Attaches a trace writer to the state
+    #[cfg(feature = "recording")]
+    pub fn with_tracer(mut self, tracer: trace::Tracer) -> Self {
+        self.set_tracer(Some(tracer));
+        self
+    }
+
+    /// Attaches/detaches a trace writer to the state. When attached, the ingressed line states are
+    /// sent to the writer on every ingress (only if any lines have been recorded), the writer
+    /// merges them into the full state in its own thread
+    #[cfg(feature = "recording")]
+    pub fn set_tracer(&mut self, tracer: Option<trace::Tracer>) {
+        self.tracer = tracer;
+        self.trace_full = true;
+    }
+
+    /// Returns pass/fail statistics (if enabled)
+    #[cfg(feature = "recording")]
+    pub fn stats(&self) -> Option<&RackStats> {
+        self.stats.as_ref()
+    }
+
+    /// Clears the recorded line states (statistics are kept)
+    #[cfg(feature = "recording")]
+    pub fn clear(&mut self) {
+        if !self.lines.is_empty() {
+            self.lines.clear();
+            self.generation = self.generation.wrapping_add(1);
+            self.trace_full = true;
+        }
+    }
+
+    /// Resets pass/fail statistics
+    #[cfg(feature = "recording")]
+    pub fn reset_stats(&mut self) {
+        if let Some(stats) = self.stats.as_mut() {
+            stats.reset();
+            self.stats_generation = self.stats_generation.wrapping_add(1);
+        }
+    }
 }
 
 /// Processor is an instance which creates logical lines
@@ -619,7 +775,8 @@ impl Processor {
     pub fn is_recording(&self) -> bool {
         self.recording.load(atomic::Ordering::SeqCst)
     }
-    /// Creates a new logical line
+    /// Creates a new logical line. When recording, the line input is recorded as well (by the
+    /// first line step)
     pub fn line<INPUT>(
         &mut self,
         name: impl Into<Cow<'static, str>>,
@@ -633,7 +790,7 @@ impl Processor {
                     entry.insert(LineState::new(name.clone()));
                 }
                 btree_map::Entry::Occupied(mut entry) => {
-                    entry.get_mut().clear();
+                    entry.get_mut().reset();
                 }
             }
         }
@@ -729,4 +886,32 @@ mod test {
         assert!(line2_active);
         state.ingress(&mut processor);
     }
+
+    #[cfg(feature = "recording")]
+    #[test]
+    fn test_generation() {
+        let mut rack = Rack::new().with_recording_enabled();
+        let mut processor = rack.processor();
+        let mut ingress = |rack: &mut Rack, temp: i32| {
+            processor
+                .line("fan_on", temp)
+                .then(action!("temp_high", |t| (t > 30).then_some(())));
+            rack.ingress(&mut processor);
+            rack.generation()
+        };
+        assert_eq!(rack.generation(), 0);
+        assert_eq!(ingress(&mut rack, 31), 1);
+        assert_eq!(ingress(&mut rack, 31), 1);
+        assert_eq!(ingress(&mut rack, 20), 2);
+        // the input is changed, the pass state is not
+        assert_eq!(ingress(&mut rack, 21), 3);
+        assert_eq!(rack.stats_generation(), 0);
+        rack.set_stats(true);
+        assert_eq!(rack.stats_generation(), 1);
+        // statistics are changed on every ingress
+        assert_eq!(ingress(&mut rack, 21), 3);
+        assert_eq!(rack.stats_generation(), 2);
+        rack.reset_stats();
+        assert_eq!(rack.stats_generation(), 3);
+    }
 }
@@ -0,0 +1,283 @@
+use std::fmt::Write as _;
+
+use serde_json::Value;
+
+use crate::{Counters, LineState, Snapshot};
+
+/// Content type of the Prometheus text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[derive(Clone, Copy)]
+enum MetricKind {
+    Gauge,
+    Counter,
+}
+
+impl MetricKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            MetricKind::Gauge => "gauge",
+            MetricKind::Counter => "counter",
+        }
+    }
+}
+
+struct Family {
+    name: &'static str,
+    help: &'static str,
+    kind: MetricKind,
+    samples: Vec<(String, f64)>,
+}
+
+impl Family {
+    fn new(name: &'static str, kind: MetricKind, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            kind,
+            samples: Vec::new(),
+        }
+    }
+}
+
+struct Families {
+    line_passed: Family,
+    line_input: Family,
+    step_passed: Family,
+    step_input: Family,
+    line_executions: Family,
+    line_passes: Family,
+    line_transitions: Family,
+    step_executions: Family,
+    step_passes: Family,
+    step_transitions: Family,
+}
+
+impl Families {
+    fn new() -> Self {
+        use MetricKind::{Counter, Gauge};
+        Self {
+            line_passed: Family::new(
+                "logicline_line_passed",
+                Gauge,
+                "Line pass state (1 if all the line steps are passed)",
+            ),
+            line_input: Family::new("logicline_line_input", Gauge, "Numeric line input"),
+            step_passed: Family::new("logicline_step_passed", Gauge, "Step pass state"),
+            step_input: Family::new("logicline_step_input", Gauge, "Numeric step input"),
+            line_executions: Family::new(
+                "logicline_line_executions_total",
+                Counter,
+                "Line executions",
+            ),
+            line_passes: Family::new("logicline_line_passes_total", Counter, "Line passes"),
+            line_transitions: Family::new(
+                "logicline_line_transitions_total",
+                Counter,
+                "Line pass state transitions",
+            ),
+            step_executions: Family::new(
+                "logicline_step_executions_total",
+                Counter,
+                "Step executions",
+            ),
+            step_passes: Family::new("logicline_step_passes_total", Counter, "Step passes"),
+            step_transitions: Family::new(
+                "logicline_step_transitions_total",
+                Counter,
+                "Step pass state transitions",
+            ),
+        }
+    }
+    fn into_array(self) -> [Family; 10] {
+        [
+            self.line_passed,
+            self.line_input,
+            self.step_passed,
+            self.step_input,
+            self.line_executions,
+            self.line_passes,
+            self.line_transitions,
+            self.step_executions,
+            self.step_passes,
+            self.step_transitions,
+        ]
+    }
+}
+
+fn escape_label(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '"' => result.push_str("\\\""),
+            '\n' => result.push_str("\\n"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_owned()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_owned()
+    } else {
+        value.to_string()
+    }
+}
+
+fn numeric(value: &Value) -> Option<f64> {
+    match value {
+        Value::Bool(b) => Some(f64::from(u8::from(*b))),
+        _ => value.as_f64(),
+    }
+}
+
+/// Step label values, steps with the same names get the step index suffix to keep the series
+/// unique (similarly to VCD signal names)
+fn step_labels<'a>(names: impl Iterator<Item = &'a str> + Clone) -> Vec<String> {
+    names
+        .clone()
+        .enumerate()
+        .map(|(i, name)| {
+            if names.clone().filter(|n| *n == name).count() > 1 {
+                format!("{}_{}", name, i)
+            } else {
+                name.to_owned()
+            }
+        })
+        .collect()
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn push_counters(
+    counters: &Counters,
+    labels: &str,
+    executions: &mut Family,
+    passes: &mut Family,
+    transitions: &mut Family,
+) {
+    executions
+        .samples
+        .push((labels.to_owned(), counters.executions() as f64));
+    passes
+        .samples
+        .push((labels.to_owned(), counters.passes() as f64));
+    transitions
+        .samples
+        .push((labels.to_owned(), counters.transitions() as f64));
+}
+
+fn collect_line(families: &mut Families, snapshot: &Snapshot, name: &str, line: &LineState) {
+    let line_labels = format!("line=\"{}\"", escape_label(name));
+    families
+        .line_passed
+        .samples
+        .push((line_labels.clone(), f64::from(u8::from(line.passed()))));
+    if let Some(input) = line.input().and_then(numeric) {
+        families
+            .line_input
+            .samples
+            .push((line_labels.clone(), input));
+    }
+    let infos: Vec<_> = line.steps().iter().flat_map(|s| s.info()).collect();
+    let labels = step_labels(infos.iter().map(|i| i.name()));
+    for (info, step) in infos.iter().zip(&labels) {
+        let step_labels = format!("{},step=\"{}\"", line_labels, escape_label(step));
+        families
+            .step_passed
+            .samples
+            .push((step_labels.clone(), f64::from(u8::from(info.passed()))));
+        if let Some(input) = numeric(info.input()) {
+            families.step_input.samples.push((step_labels, input));
+        }
+    }
+    let Some(stats) = snapshot.stats().and_then(|s| s.line_stats(name)) else {
+        return;
+    };
+    push_counters(
+        stats.counters(),
+        &line_labels,
+        &mut families.line_executions,
+        &mut families.line_passes,
+        &mut families.line_transitions,
+    );
+    let labels = step_labels(stats.steps().iter().map(crate::StepStats::name));
+    for (step_stats, step) in stats.steps().iter().zip(&labels) {
+        push_counters(
+            step_stats.counters(),
+            &format!("{},step=\"{}\"", line_labels, escape_label(step)),
+            &mut families.step_executions,
+            &mut families.step_passes,
+            &mut families.step_transitions,
+        );
+    }
+}
+
+/// Renders a snapshot in the Prometheus text exposition format.
+///
+/// Line and step pass states are exported as `logicline_line_passed{line}` and
+/// `logicline_step_passed{line,step}` gauges (`1` - passed, `0` - not passed), numeric (and
+/// boolean) inputs as `logicline_line_input` and `logicline_step_input` gauges. If the snapshot
+/// contains statistics, execution, pass and transition counters are exported as well
+/// (`logicline_line_executions_total`, `logicline_step_transitions_total` etc.).
+pub fn render(snapshot: &Snapshot) -> String {
+    let mut families = Families::new();
+    for (name, line) in snapshot.lines() {
+        collect_line(&mut families, snapshot, name, line);
+    }
+    let mut out = String::new();
+    for family in families.into_array() {
+        if family.samples.is_empty() {
+            continue;
+        }
+        let _ = writeln!(out, "# HELP {} {}", family.name, family.help);
+        let _ = writeln!(out, "# TYPE {} {}", family.name, family.kind.as_str());
+        for (labels, value) in family.samples {
+            let _ = writeln!(out, "{}{{{}}} {}", family.name, labels, format_value(value));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Rack, action};
+
+    #[test]
+    fn test_metrics() {
+        let mut rack = Rack::new().with_recording_enabled().with_stats_enabled();
+        for temp in [20, 31] {
+            let mut processor = rack.processor();
+            processor
+                .line("fan \"on\"", temp)
+                .then_any(
+                    action!("temp_high", |t| (t > 30).then_some(())),
+                    action!("temp_high", |t| (t > 40).then_some(())),
+                )
+                .then(action!("fan_on", |()| Some(())));
+            rack.ingress(&mut processor);
+        }
+        let metrics = super::render(&rack.snapshot());
+        for expected in [
+            "# TYPE logicline_line_passed gauge\nlogicline_line_passed{line=\"fan \\\"on\\\"\"} 1\n",
+            "logicline_line_input{line=\"fan \\\"on\\\"\"} 31\n",
+            "logicline_step_passed{line=\"fan \\\"on\\\"\",step=\"temp_high_0\"} 1\n",
+            "logicline_step_passed{line=\"fan \\\"on\\\"\",step=\"temp_high_1\"} 0\n",
+            "logicline_step_input{line=\"fan \\\"on\\\"\",step=\"temp_high_1\"} 31\n",
+            "# TYPE logicline_line_executions_total counter\n\
+             logicline_line_executions_total{line=\"fan \\\"on\\\"\"} 2\n",
+            "logicline_line_transitions_total{line=\"fan \\\"on\\\"\"} 1\n",
+            "logicline_step_passes_total{line=\"fan \\\"on\\\"\",step=\"temp_high_0\"} 1\n",
+        ] {
+            assert!(
+                metrics.contains(expected),
+                "{} not found in\n{}",
+                expected,
+                metrics
+            );
+        }
+    }
+}
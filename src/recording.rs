@@ -1,25 +1,44 @@
 use core::fmt;
-use std::{borrow::Cow, collections::BTreeMap, sync::Arc};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::BTreeMap,
+    io,
+    sync::{Arc, OnceLock},
+};
 
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{
+    Value,
+    ser::{CompactFormatter, Formatter},
+};
 
-use crate::Rack;
+use crate::{Rack, RackStats};
 
 /// Input kind, flow: taken from the previous action, external: specified by the user
-#[derive(Deserialize, Serialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum InputKind {
     /// Taken from the previous action
+    #[default]
     Flow,
     /// Specified by the user
     External,
 }
 
 /// State of the logical line
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct LineState {
+    /// Line name
     name: Cow<'static, str>,
+    /// The initial line input (since format version 2)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Value>"))]
+    // shared between snapshots, so the input is converted to JSON only once
+    input: Option<Arc<RecordedInput>>,
+    /// Line steps, `OR` steps are serialized as arrays
     steps: Vec<StepState>,
 }
 
@@ -72,6 +91,7 @@ impl LineState {
     pub(crate) fn new(name: impl Into<Cow<'static, str>>) -> Self {
         LineState {
             name: name.into(),
+            input: None,
             steps: Vec::new(),
         }
     }
@@ -79,6 +99,13 @@ impl LineState {
     pub fn name(&self) -> &str {
         self.name.as_ref()
     }
+    /// The initial line input (the value passed to [`crate::Processor::line`]), serialized as
+    /// [`serde_json::Value`] on the first access. The input is recorded by the first line step, so
+    /// it is `None` for lines without steps and for lines deserialized from snapshots which have
+    /// been recorded without line inputs
+    pub fn input(&self) -> Option<&Value> {
+        self.input.as_deref().map(RecordedInput::value)
+    }
     /// Steps states of the line
     pub fn steps(&self) -> &[StepState] {
         &self.steps
@@ -87,6 +114,12 @@ impl LineState {
     pub fn steps_mut(&mut self) -> &mut [StepState] {
         &mut self.steps
     }
+    /// Has the line been passed (each step has got at least one passed action)
+    pub fn passed(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|step| step.info().iter().any(|s| s.passed()))
+    }
     //pub(crate) fn push_step_state<INPUT: Serialize>(
     //&mut self,
     //name: impl Into<Cow<'static, str>>,
@@ -106,22 +139,30 @@ impl LineState {
     pub(crate) fn push_step_state(
         &mut self,
         name: impl Into<Cow<'static, str>>,
-        input: Value,
+        input: RecordedInput,
         input_kind: InputKind,
         passed: bool,
     ) {
         self.steps
-            .push(StepState::Single(StepStateInfo::new_with_serialized_input(
+            .push(StepState::Single(StepStateInfo::new_with_recorded_input(
                 name, input, input_kind, passed,
             )));
     }
-    pub(crate) fn clear(&mut self) {
+    pub(crate) fn reset(&mut self) {
+        self.input = None;
         self.steps.clear();
     }
+    pub(crate) fn has_input(&self) -> bool {
+        self.input.is_some()
+    }
+    pub(crate) fn set_input(&mut self, input: RecordedInput) {
+        self.input = Some(Arc::new(input));
+    }
 }
 
 /// Line step state
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(untagged)]
 pub enum StepState {
     /// Single step state (single flow)
@@ -157,6 +198,7 @@ impl StepState {
 
 /// Single step state information
 #[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct StepStateInfo {
     #[serde(flatten)]
     inner: Arc<StepStateInner>,
@@ -170,6 +212,12 @@ impl Clone for StepStateInfo {
     }
 }
 
+impl PartialEq for StepStateInfo {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner) || self.inner == other.inner
+    }
+}
+
 impl fmt::Debug for StepStateInfo {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         f.debug_struct("StepState")
@@ -180,14 +228,191 @@ impl fmt::Debug for StepStateInfo {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 struct StepStateInner {
+    /// Action name
     name: Cow<'static, str>,
-    input: Value,
+    /// Action input
+    #[cfg_attr(feature = "schema", schemars(with = "Value"))]
+    input: RecordedInput,
+    /// Input kind (may be missing in format version 1, `flow` by default)
+    #[serde(default)]
     input_kind: InputKind,
+    /// Has the action been passed
     passed: bool,
 }
 
+const INLINE_CAPACITY: usize = 22;
+
+thread_local! {
+    static PACK_BUF: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Clone)]
+enum Packed {
+    Inline { len: u8, buf: [u8; INLINE_CAPACITY] },
+    Heap(Box<[u8]>),
+}
+
+impl Packed {
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            Packed::Inline { len, buf } => &buf[..usize::from(*len)],
+            Packed::Heap(buf) => buf,
+        }
+    }
+}
+
+/// Compact JSON formatter, which refuses 128-bit integers out of the 64-bit range (except in map
+/// keys), as [`serde_json::to_value`] does
+#[derive(Default)]
+struct InputFormatter {
+    key: bool,
+}
+
+impl InputFormatter {
+    fn out_of_range() -> io::Error {
+        io::Error::other("number out of range")
+    }
+}
+
+impl Formatter for InputFormatter {
+    fn write_i128<W>(&mut self, writer: &mut W, value: i128) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        if !self.key && i64::try_from(value).is_err() && u64::try_from(value).is_err() {
+            return Err(Self::out_of_range());
+        }
+        CompactFormatter.write_i128(writer, value)
+    }
+    fn write_u128<W>(&mut self, writer: &mut W, value: u128) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        if !self.key && u64::try_from(value).is_err() {
+            return Err(Self::out_of_range());
+        }
+        CompactFormatter.write_u128(writer, value)
+    }
+    fn begin_object_key<W>(&mut self, writer: &mut W, first: bool) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        self.key = true;
+        CompactFormatter.begin_object_key(writer, first)
+    }
+    fn end_object_key<W>(&mut self, writer: &mut W) -> io::Result<()>
+    where
+        W: ?Sized + io::Write,
+    {
+        self.key = false;
+        CompactFormatter.end_object_key(writer)
+    }
+}
+
+/// Recorded step input. To keep the hot path cheap, the input is serialized into a compact JSON
+/// buffer when recorded (no value tree is allocated) and converted to [`serde_json::Value`] only
+/// on the first access (e.g. when a snapshot is serialized). The conversion gives the same result
+/// as [`serde_json::to_value`] (e.g. integer map keys are converted to strings).
+#[derive(Clone)]
+pub(crate) struct RecordedInput {
+    packed: Option<Packed>,
+    value: OnceLock<Value>,
+}
+
+impl PartialEq for RecordedInput {
+    fn eq(&self, other: &Self) -> bool {
+        // packed inputs are compared without decoding
+        match (&self.packed, &other.packed) {
+            (Some(a), Some(b)) => a.as_bytes() == b.as_bytes(),
+            _ => self.value() == other.value(),
+        }
+    }
+}
+
+impl RecordedInput {
+    pub(crate) fn null() -> Self {
+        Self::from_value(Value::Null)
+    }
+    pub(crate) fn from_value(value: Value) -> Self {
+        RecordedInput {
+            packed: None,
+            value: OnceLock::from(value),
+        }
+    }
+    pub(crate) fn pack<T: Serialize + ?Sized>(input: &T) -> Self {
+        PACK_BUF.with_borrow_mut(|buf| {
+            buf.clear();
+            let mut serializer =
+                serde_json::Serializer::with_formatter(&mut *buf, InputFormatter::default());
+            if input.serialize(&mut serializer).is_err() {
+                return Self::null();
+            }
+            let packed = if buf.len() <= INLINE_CAPACITY {
+                let mut inline = [0; INLINE_CAPACITY];
+                inline[..buf.len()].copy_from_slice(buf);
+                #[allow(clippy::cast_possible_truncation)]
+                Packed::Inline {
+                    len: buf.len() as u8,
+                    buf: inline,
+                }
+            } else {
+                Packed::Heap(buf.as_slice().into())
+            };
+            RecordedInput {
+                packed: Some(packed),
+                value: OnceLock::new(),
+            }
+        })
+    }
+    pub(crate) fn value(&self) -> &Value {
+        self.value.get_or_init(|| {
+            self.packed
+                .as_ref()
+                .and_then(|p| {
+                    // the buffer has been written by serde_json, so it is valid JSON, but may be
+                    // nested deeper than the default recursion limit
+                    let mut deserializer = serde_json::Deserializer::from_slice(p.as_bytes());
+                    deserializer.disable_recursion_limit();
+                    Value::deserialize(&mut deserializer).ok()
+                })
+                .unwrap_or_default()
+        })
+    }
+}
+
+impl Default for RecordedInput {
+    fn default() -> Self {
+        Self::null()
+    }
+}
+
+impl fmt::Debug for RecordedInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value().fmt(f)
+    }
+}
+
+impl Serialize for RecordedInput {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.value().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RecordedInput {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Value::deserialize(deserializer).map(Self::from_value)
+    }
+}
+
 impl StepStateInfo {
     /// Returns modified version of the step state info
     pub fn to_modified(
@@ -200,7 +425,7 @@ impl StepStateInfo {
         StepStateInfo {
             inner: Arc::new(StepStateInner {
                 name: name.map_or_else(|| self.inner.name.clone(), |n| n.to_owned().into()),
-                input: input.unwrap_or_else(|| self.inner.input.clone()),
+                input: input.map_or_else(|| self.inner.input.clone(), RecordedInput::from_value),
                 input_kind: input_kind.unwrap_or(self.inner.input_kind),
                 passed: passed.unwrap_or(self.inner.passed),
             }),
@@ -215,15 +440,15 @@ impl StepStateInfo {
         StepStateInfo {
             inner: Arc::new(StepStateInner {
                 name: name.into(),
-                input: serde_json::to_value(input).unwrap_or_default(),
+                input: RecordedInput::pack(&input),
                 input_kind,
                 passed,
             }),
         }
     }
-    pub(crate) fn new_with_serialized_input(
+    pub(crate) fn new_with_recorded_input(
         name: impl Into<Cow<'static, str>>,
-        input: Value,
+        input: RecordedInput,
         input_kind: InputKind,
         passed: bool,
     ) -> Self {
@@ -240,9 +465,9 @@ impl StepStateInfo {
     pub fn name(&self) -> &str {
         self.inner.name.as_ref()
     }
-    /// Step input (serialized as [`serde_json::Value`])
+    /// Step input (serialized as [`serde_json::Value`] on the first access)
     pub fn input(&self) -> &Value {
-        &self.inner.input
+        self.inner.input.value()
     }
     /// Step input kind (flow or external)
     pub fn input_kind(&self) -> InputKind {
@@ -272,13 +497,69 @@ pub trait SnapshotFormatter: Send + Sync {
     fn format(&self, snapshot: Snapshot) -> Snapshot;
 }
 
-/// State snapshot
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+/// Current snapshot format version
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 2;
+
+// snapshots, serialized before the version field has been introduced
+fn legacy_format_version() -> u32 {
+    1
+}
+
+/// State snapshot.
+///
+/// Format versions:
+///
+/// * 1 - no version field, lines and steps only
+///
+/// * 2 - `version` field, optional line inputs and rack statistics
+///
+/// Snapshots of the older versions are deserialized with the missing fields set to `None`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Snapshot {
+    /// Snapshot format version
+    #[serde(default = "legacy_format_version")]
+    version: u32,
+    /// Line states, sorted by line names
     pub(crate) lines: BTreeMap<Cow<'static, str>, LineState>,
+    /// Pass/fail statistics (if enabled for the rack, since format version 2)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) stats: Option<RackStats>,
+}
+
+impl Default for Snapshot {
+    fn default() -> Self {
+        Self::new(<_>::default(), None)
+    }
 }
 
 impl Snapshot {
+    pub(crate) fn new(
+        lines: BTreeMap<Cow<'static, str>, LineState>,
+        stats: Option<RackStats>,
+    ) -> Self {
+        Self {
+            version: SNAPSHOT_FORMAT_VERSION,
+            lines,
+            stats,
+        }
+    }
+    /// Keeps the lines which match the predicate only (with their statistics)
+    #[cfg(feature = "exporter")]
+    pub(crate) fn filtered<P>(mut self, predicate: P) -> Self
+    where
+        P: Fn(&LineState) -> bool,
+    {
+        self.lines.retain(|_, line| predicate(line));
+        if let Some(stats) = self.stats.as_mut() {
+            *stats = stats.filtered(&self.lines);
+        }
+        self
+    }
+    /// Format version the snapshot has been created/deserialized with
+    pub fn version(&self) -> u32 {
+        self.version
+    }
     /// State of the line
     pub fn line_state(&self, name: &str) -> Option<&LineState> {
         self.lines.get(name)
@@ -295,6 +576,44 @@ impl Snapshot {
     pub fn lines_mut(&mut self) -> &mut BTreeMap<Cow<'static, str>, LineState> {
         &mut self.lines
     }
+    /// Pass/fail statistics (if enabled for the rack)
+    pub fn stats(&self) -> Option<&RackStats> {
+        self.stats.as_ref()
+    }
+}
+
+#[cfg(feature = "schema")]
+impl Snapshot {
+    /// JSON Schema of the snapshot format
+    pub fn schema() -> Value {
+        serde_json::to_value(schemars::schema_for!(Snapshot)).unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl Snapshot {
+    /// Serializes the snapshot to CBOR
+    pub fn to_cbor(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut buf = Vec::new();
+        ciborium::into_writer(self, &mut buf)?;
+        Ok(buf)
+    }
+    /// Deserializes a snapshot from CBOR
+    pub fn from_cbor(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(ciborium::from_reader(data)?)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl Snapshot {
+    /// Serializes the snapshot to MessagePack (structures are encoded as maps)
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(rmp_serde::to_vec_named(self)?)
+    }
+    /// Deserializes a snapshot from MessagePack
+    pub fn from_msgpack(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(rmp_serde::from_slice(data)?)
+    }
 }
 
 impl fmt::Display for Snapshot {
@@ -308,3 +627,199 @@ impl fmt::Display for Snapshot {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use serde::Serialize;
+
+    use super::RecordedInput;
+
+    #[test]
+    fn test_recorded_input() {
+        #[derive(Serialize)]
+        enum Mode {
+            Auto,
+            Manual(u8),
+        }
+        #[derive(Serialize)]
+        struct Data {
+            temperature: f32,
+            values: Vec<f64>,
+            mode: Mode,
+            manual: Mode,
+            name: &'static str,
+            missing: Option<u16>,
+            tuple: (i64, bool),
+        }
+        let data = Data {
+            temperature: 31.5,
+            values: vec![1.0, -2.5, 1e10],
+            mode: Mode::Auto,
+            manual: Mode::Manual(3),
+            name: "fan",
+            missing: None,
+            tuple: (-5, true),
+        };
+        assert_eq!(
+            RecordedInput::pack(&data).value(),
+            &serde_json::to_value(&data).unwrap()
+        );
+        assert_eq!(RecordedInput::pack(&1u8).value(), &serde_json::json!(1));
+        assert_eq!(RecordedInput::pack(&()).value(), &serde_json::Value::Null);
+        let registers: std::collections::HashMap<u16, f64> = [(40001, 1.5)].into();
+        assert_eq!(
+            RecordedInput::pack(&registers).value(),
+            &serde_json::json!({"40001": 1.5})
+        );
+        assert_eq!(
+            RecordedInput::pack(&u128::from(u64::MAX)).value(),
+            &serde_json::to_value(u128::from(u64::MAX)).unwrap()
+        );
+        // out of the JSON value range, as with to_value
+        assert_eq!(
+            RecordedInput::pack(&(u128::from(u64::MAX) + 1)).value(),
+            &serde_json::Value::Null
+        );
+        assert_eq!(
+            RecordedInput::pack(&i128::MIN).value(),
+            &serde_json::Value::Null
+        );
+        let keys: std::collections::BTreeMap<u128, u8> = [(u128::MAX, 1)].into();
+        assert_eq!(
+            RecordedInput::pack(&keys).value(),
+            &serde_json::to_value(&keys).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_recorded_input_nested() {
+        let mut nested = serde_json::json!(1);
+        for _ in 0..200 {
+            nested = serde_json::json!({ "inner": [nested] });
+        }
+        assert_eq!(RecordedInput::pack(&nested).value(), &nested);
+    }
+
+    #[test]
+    fn test_line_input_shared() {
+        let mut rack = crate::Rack::new().with_recording_enabled();
+        let mut processor = rack.processor();
+        processor
+            .line("fan_on", 31)
+            .then(crate::action!("temp_high", |t| (t > 30).then_some(())));
+        rack.ingress(&mut processor);
+        let first = rack.snapshot();
+        let second = rack.snapshot();
+        let input = |snapshot: &crate::Snapshot| {
+            snapshot
+                .line_state("fan_on")
+                .unwrap()
+                .input
+                .clone()
+                .unwrap()
+        };
+        assert!(std::sync::Arc::ptr_eq(&input(&first), &input(&second)));
+        assert_eq!(
+            first.line_state("fan_on").unwrap().input(),
+            Some(&31.into())
+        );
+    }
+
+    #[test]
+    fn test_line_input_first_step() {
+        struct NotSerializable;
+        let mut rack = crate::Rack::new().with_recording_enabled();
+        let mut processor = rack.processor();
+        // line inputs do not have to be serializable unless the line has steps
+        processor.line("idle", NotSerializable);
+        processor
+            .line("fan_on", 31)
+            .then(crate::action!("temp_high", |t| (t > 30).then_some(t + 1)))
+            .then(crate::action!("fan_on", |_| Some(())));
+        rack.ingress(&mut processor);
+        assert_eq!(rack.line_state("idle").unwrap().input(), None);
+        assert_eq!(rack.line_state("fan_on").unwrap().input(), Some(&31.into()));
+    }
+
+    #[test]
+    fn test_snapshot_versions() {
+        let legacy: crate::Snapshot = serde_json::from_str(
+            r#"{"lines":{"fan_on":{"name":"fan_on","steps":[
+                [{"name":"temp_high","input":31,"passed":true,"input_kind":"flow"},
+                 {"name":"temp_critical","input":31,"passed":false,"input_kind":"flow"}],
+                {"name":"fan_on","input":null,"passed":true}]}}}"#,
+        )
+        .unwrap();
+        assert_eq!(legacy.version(), 1);
+        let line = legacy.line_state("fan_on").unwrap();
+        assert!(line.input().is_none());
+        assert!(line.passed());
+        assert!(legacy.stats().is_none());
+        let mut rack = crate::Rack::new().with_recording_enabled();
+        let mut processor = rack.processor();
+        processor
+            .line("fan_on", 31)
+            .then(crate::action!("temp_high", |t| (t > 30).then_some(())));
+        rack.ingress(&mut processor);
+        let value = serde_json::to_value(rack.snapshot()).unwrap();
+        assert_eq!(value["version"], super::SNAPSHOT_FORMAT_VERSION);
+        let snapshot: crate::Snapshot = serde_json::from_value(value).unwrap();
+        assert_eq!(snapshot.version(), super::SNAPSHOT_FORMAT_VERSION);
+    }
+
+    #[cfg(feature = "schema")]
+    #[test]
+    fn test_schema() {
+        let schema = crate::Snapshot::schema();
+        assert_eq!(schema["title"], "Snapshot");
+        assert_eq!(schema["properties"]["version"]["type"], "integer");
+        assert!(schema["$defs"]["LineState"].is_object());
+        assert!(schema["$defs"]["StepState"]["anyOf"].is_array());
+    }
+
+    #[cfg(any(feature = "cbor", feature = "msgpack"))]
+    fn sample_snapshot() -> crate::Snapshot {
+        let mut rack = crate::Rack::new()
+            .with_recording_enabled()
+            .with_stats_enabled();
+        let mut processor = rack.processor();
+        let values = [1.5, 2.0, -3.25];
+        processor
+            .line("pumps", values)
+            .then_any(
+                crate::action!("any_high", |v: [f64; 3]| v
+                    .iter()
+                    .any(|v| *v > 2.0)
+                    .then_some(())),
+                crate::action!("all_low", |v: [f64; 3]| v
+                    .iter()
+                    .all(|v| *v < 0.0)
+                    .then_some(())),
+            )
+            .then(crate::action!("pump_on", |()| Some(())));
+        rack.ingress(&mut processor);
+        rack.snapshot()
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_cbor() {
+        let snapshot = sample_snapshot();
+        let decoded = crate::Snapshot::from_cbor(&snapshot.to_cbor().unwrap()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&snapshot).unwrap(),
+            serde_json::to_value(&decoded).unwrap()
+        );
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_msgpack() {
+        let snapshot = sample_snapshot();
+        let decoded = crate::Snapshot::from_msgpack(&snapshot.to_msgpack().unwrap()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&snapshot).unwrap(),
+            serde_json::to_value(&decoded).unwrap()
+        );
+    }
+}
@@ -0,0 +1,275 @@
+use core::fmt;
+
+use crate::{LineState, Processor, Rack, Snapshot, trace::TraceRecord};
+
+/// A line, which step outcomes differ from the original recording
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    timestamp: Option<f64>,
+    expected: LineState,
+    actual: Option<LineState>,
+}
+
+impl Mismatch {
+    /// Line name
+    pub fn line(&self) -> &str {
+        self.expected.name()
+    }
+    /// Trace record timestamp (if checked with a trace)
+    pub fn timestamp(&self) -> Option<f64> {
+        self.timestamp
+    }
+    /// The original line state
+    pub fn expected(&self) -> &LineState {
+        &self.expected
+    }
+    /// The line state, produced by the new code. `None` if the line has not been re-created
+    pub fn actual(&self) -> Option<&LineState> {
+        self.actual.as_ref()
+    }
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}", self.line())?;
+        if let Some(timestamp) = self.timestamp {
+            write!(f, " at {}", timestamp)?;
+        }
+        writeln!(f, ":")?;
+        writeln!(f, "  expected: {}", self.expected)?;
+        match self.actual {
+            Some(ref actual) => write!(f, "  actual:   {}", actual),
+            None => write!(f, "  actual:   <not processed>"),
+        }
+    }
+}
+
+/// Logic regression report
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    checked: usize,
+    skipped: usize,
+    mismatches: Vec<Mismatch>,
+}
+
+impl Report {
+    /// Number of checked lines
+    pub fn checked(&self) -> usize {
+        self.checked
+    }
+    /// Number of lines skipped as recorded without inputs
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+    /// Lines which step outcomes differ from the original recording
+    pub fn mismatches(&self) -> &[Mismatch] {
+        &self.mismatches
+    }
+    /// Returns `true` if no mismatches have been found
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} lines checked, {} skipped, {} mismatches",
+            self.checked,
+            self.skipped,
+            self.mismatches.len()
+        )?;
+        for mismatch in &self.mismatches {
+            write!(f, "\n{}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Logic regression harness. Feeds the recorded line inputs (see [`LineState::input`]) back
+/// through the new line code and reports every line which step outcomes (names and pass states)
+/// differ from the original recording.
+///
+/// The line code is provided as a closure which gets a recording processor and the original line
+/// state. The closure must re-create the line with the same name, using the deserialized recorded
+/// input:
+///
+/// ```rust,ignore
+/// let mut regression = Regression::new();
+/// regression.check_trace(TraceReader::open("trace.jsonl")?.map(Result::unwrap), |processor, line| {
+///     if line.name() == "fan_on" {
+///         let temp: f64 = serde_json::from_value(line.input().unwrap().clone()).unwrap();
+///         processor
+///             .line("fan_on", temp)
+///             .then(action!("temp_high", |t| (t > 32.0).then_some(())));
+///     }
+/// });
+/// assert!(regression.report().is_ok(), "{}", regression.report());
+/// ```
+pub struct Regression {
+    rack: Rack,
+    report: Report,
+}
+
+impl Default for Regression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Regression {
+    /// Creates a new regression harness
+    pub fn new() -> Self {
+        Self {
+            rack: Rack::new().with_recording_enabled(),
+            report: Report::default(),
+        }
+    }
+    /// Checks all the lines of a snapshot
+    pub fn check_snapshot<F>(&mut self, snapshot: &Snapshot, f: F)
+    where
+        F: FnMut(&mut Processor, &LineState),
+    {
+        self.check(None, snapshot, None, f);
+    }
+    /// Checks the lines of trace records. Records contain all the rack lines, so only the lines
+    /// which have been changed since the previous record are checked
+    pub fn check_trace<I, F>(&mut self, records: I, mut f: F)
+    where
+        I: IntoIterator<Item = TraceRecord>,
+        F: FnMut(&mut Processor, &LineState),
+    {
+        let mut previous: Option<TraceRecord> = None;
+        for record in records {
+            self.check(
+                Some(record.timestamp()),
+                record.snapshot(),
+                previous.as_ref().map(TraceRecord::snapshot),
+                &mut f,
+            );
+            previous = Some(record);
+        }
+    }
+    fn check<F>(
+        &mut self,
+        timestamp: Option<f64>,
+        snapshot: &Snapshot,
+        previous: Option<&Snapshot>,
+        mut f: F,
+    ) where
+        F: FnMut(&mut Processor, &LineState),
+    {
+        for line in snapshot.lines().values() {
+            if previous.is_some_and(|p| p.line_state(line.name()) == Some(line)) {
+                continue;
+            }
+            if line.input().is_none() {
+                self.report.skipped += 1;
+                continue;
+            }
+            self.report.checked += 1;
+            let mut processor = self.rack.processor();
+            f(&mut processor, line);
+            let actual = processor.line_state(line.name());
+            if actual.is_none_or(|actual| !same_outcomes(line, actual)) {
+                self.report.mismatches.push(Mismatch {
+                    timestamp,
+                    expected: line.clone(),
+                    actual: actual.cloned(),
+                });
+            }
+        }
+    }
+    /// The regression report
+    pub fn report(&self) -> &Report {
+        &self.report
+    }
+    /// Converts the harness into the report
+    pub fn into_report(self) -> Report {
+        self.report
+    }
+}
+
+fn same_outcomes(a: &LineState, b: &LineState) -> bool {
+    a.steps().len() == b.steps().len()
+        && a.steps().iter().zip(b.steps()).all(|(a, b)| {
+            let (a, b) = (a.info(), b.info());
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b)
+                    .all(|(a, b)| a.name() == b.name() && a.passed() == b.passed())
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::Regression;
+    use crate::{Processor, Rack, action, trace::TraceRecord};
+
+    fn fan_on(processor: &mut Processor, temp: f64, threshold: f64) {
+        processor
+            .line("fan_on", temp)
+            .then(action!("temp_high", |t| (t > threshold).then_some(())))
+            .then(action!("fan_on", |()| Some(())));
+    }
+
+    #[test]
+    fn test_regression() {
+        let mut rack = Rack::new().with_recording_enabled();
+        let mut snapshots = Vec::new();
+        for temp in [25.0, 31.0, 33.0] {
+            let mut processor = rack.processor();
+            fan_on(&mut processor, temp, 30.0);
+            rack.ingress(&mut processor);
+            snapshots.push(rack.snapshot());
+        }
+        let mut regression = Regression::new();
+        for snapshot in &snapshots {
+            regression.check_snapshot(snapshot, |processor, line| {
+                let temp = serde_json::from_value(line.input().unwrap().clone()).unwrap();
+                fan_on(processor, temp, 32.0);
+            });
+        }
+        let report = regression.report();
+        assert_eq!(report.checked(), 3);
+        assert_eq!(report.mismatches().len(), 1);
+        assert_eq!(report.mismatches()[0].line(), "fan_on");
+        assert_eq!(
+            report.mismatches()[0].expected().input(),
+            Some(&serde_json::json!(31.0))
+        );
+    }
+
+    #[test]
+    fn test_regression_trace() {
+        let mut rack = Rack::new().with_recording_enabled();
+        let mut records = Vec::new();
+        for (timestamp, temp) in [(0.0, 25.0), (1.0, 31.0), (2.0, 31.0), (3.0, 33.0)] {
+            let mut processor = rack.processor();
+            fan_on(&mut processor, temp, 30.0);
+            processor
+                .line("fan_off", 15.0)
+                .then(action!("temp_low", |t| (t < 20.0).then_some(())));
+            rack.ingress(&mut processor);
+            records.push(TraceRecord::new(timestamp, rack.snapshot()));
+        }
+        let mut regression = Regression::new();
+        regression.check_trace(records, |processor, line| {
+            let temp = serde_json::from_value(line.input().unwrap().clone()).unwrap();
+            match line.name() {
+                "fan_on" => fan_on(processor, temp, 32.0),
+                _ => {
+                    processor
+                        .line("fan_off", temp)
+                        .then(action!("temp_low", |t| (t < 20.0).then_some(())));
+                }
+            }
+        });
+        let report = regression.report();
+        // the unchanged lines of the records are not checked again
+        assert_eq!(report.checked(), 4);
+        assert_eq!(report.mismatches().len(), 1);
+        assert_eq!(report.mismatches()[0].timestamp(), Some(1.0));
+    }
+}
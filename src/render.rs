@@ -0,0 +1,147 @@
+use serde_json::Value;
+
+use crate::{LineState, StepStateInfo};
+
+/// [Graphviz](https://graphviz.org/) DOT renderer
+pub mod dot;
+/// Standalone HTML reports
+pub mod html;
+/// Text ladder-diagram renderer
+pub mod ladder;
+/// [Mermaid](https://mermaid.js.org/) flowchart renderer
+pub mod mermaid;
+/// [PLCopen](https://plcopen.org/) TC6 XML Ladder Diagram export
+pub mod plcopen;
+/// IEC 61131-3 Structured Text skeleton export
+pub mod st;
+
+const MAX_INPUT_LABEL_LEN: usize = 40;
+
+/// Step action status, used by the renderers
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Status {
+    /// The action has been passed
+    Passed,
+    /// The action has been executed but not passed
+    Failed,
+    /// The action has not been executed as the line has been broken at one of the previous steps
+    Skipped,
+}
+
+impl Status {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Status::Passed => "passed",
+            Status::Failed => "failed",
+            Status::Skipped => "skipped",
+        }
+    }
+}
+
+/// Returns the line steps with their actions and statuses
+pub(crate) fn line_steps(line: &LineState) -> Vec<Vec<(&StepStateInfo, Status)>> {
+    let mut reached = true;
+    line.steps()
+        .iter()
+        .map(|step| {
+            let infos = step.info();
+            let step_reached = reached;
+            reached = reached && infos.iter().any(|i| i.passed());
+            infos
+                .into_iter()
+                .map(|info| {
+                    let status = if info.passed() {
+                        Status::Passed
+                    } else if step_reached {
+                        Status::Failed
+                    } else {
+                        Status::Skipped
+                    };
+                    (info, status)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Formats a step/line input for labels, `None` for null inputs. Long inputs are truncated
+pub(crate) fn input_label(input: &Value) -> Option<String> {
+    if input.is_null() {
+        return None;
+    }
+    let mut label = input.to_string();
+    if label.chars().count() > MAX_INPUT_LABEL_LEN {
+        label = label.chars().take(MAX_INPUT_LABEL_LEN - 1).collect();
+        label.push('…');
+    }
+    Some(label)
+}
+
+/// Formats a UNIX timestamp as UTC date/time (`YYYY-MM-DD<separator>HH:MM:SS.mmm`)
+pub(crate) fn format_timestamp(timestamp: f64, separator: char) -> String {
+    #[allow(clippy::cast_possible_truncation)]
+    let millis = (timestamp * 1000.0).round() as i64;
+    let secs = millis.div_euclid(1000);
+    let days = secs.div_euclid(86_400);
+    let day_secs = secs.rem_euclid(86_400);
+    // days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let yoe = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}{}{:02}:{:02}:{:02}.{:03}",
+        year,
+        month,
+        day,
+        separator,
+        day_secs / 3600,
+        day_secs % 3600 / 60,
+        day_secs % 60,
+        millis.rem_euclid(1000)
+    )
+}
+
+/// Escapes text for XML/HTML element contents and attribute values
+pub(crate) fn escape_xml(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            // &apos; is not defined in HTML 4
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Converts a name into a valid IEC 61131-3 identifier (letters, digits and single underscores,
+/// must not start with a digit)
+pub(crate) fn identifier(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            result.push(c);
+        } else if !result.is_empty() && !result.ends_with('_') {
+            result.push('_');
+        }
+    }
+    while result.ends_with('_') {
+        result.pop();
+    }
+    if result.is_empty() {
+        result.push_str("unnamed");
+    } else if result.starts_with(|c: char| c.is_ascii_digit()) {
+        result.insert(0, '_');
+    }
+    result
+}
@@ -0,0 +1,129 @@
+use std::fmt::Write as _;
+
+use super::{Status, input_label, line_steps};
+use crate::{InputKind, LineState, Snapshot};
+
+const HEADER: &str = "digraph logicline {
+  rankdir=LR;
+  node [shape=box, style=\"rounded,filled\", fontname=\"Helvetica\"];
+  edge [arrowsize=0.6];
+";
+
+fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+fn fill_color(status: Status) -> &'static str {
+    match status {
+        Status::Passed => "#b7e4c7",
+        Status::Failed => "#f4a3a3",
+        Status::Skipped => "#e9ecef",
+    }
+}
+
+fn write_line(out: &mut String, line_no: usize, line: &LineState) {
+    let _ = writeln!(out, "  subgraph cluster_{} {{", line_no);
+    let _ = writeln!(out, "    label=\"{}\";", escape(line.name()));
+    let _ = writeln!(out, "    style=rounded;");
+    let start = format!("l{}_in", line_no);
+    let start_label = line
+        .input()
+        .and_then(input_label)
+        .map_or_else(String::new, |i| escape(&i));
+    let _ = writeln!(
+        out,
+        "    {} [shape=cds, style=filled, fillcolor=\"#dee2e6\", label=\"{}\"];",
+        start, start_label
+    );
+    let mut prev = vec![start];
+    for (step_no, step) in line_steps(line).into_iter().enumerate() {
+        let mut current = Vec::with_capacity(step.len());
+        for (action_no, (info, status)) in step.into_iter().enumerate() {
+            let id = format!("l{}_s{}_{}", line_no, step_no, action_no);
+            let mut label = escape(info.name());
+            if let Some(input) = input_label(info.input()) {
+                label.push_str("\\n");
+                if info.input_kind() == InputKind::External {
+                    label.push_str("→ ");
+                }
+                label.push_str(&escape(&input));
+            }
+            let _ = writeln!(
+                out,
+                "    {} [label=\"{}\", fillcolor=\"{}\", tooltip=\"{}\"];",
+                id,
+                label,
+                fill_color(status),
+                status.as_str()
+            );
+            for p in &prev {
+                let _ = writeln!(out, "    {} -> {};", p, id);
+            }
+            current.push(id);
+        }
+        if !current.is_empty() {
+            prev = current;
+        }
+    }
+    let _ = writeln!(out, "  }}");
+}
+
+/// Renders a snapshot as a DOT graph. Lines are rendered as clusters, steps as nodes, `OR` steps
+/// as parallel branches. The nodes are colored according to the step pass state, step inputs are
+/// added to the node labels.
+pub fn render_snapshot(snapshot: &Snapshot) -> String {
+    let mut out = HEADER.to_owned();
+    for (line_no, line) in snapshot.lines().values().enumerate() {
+        write_line(&mut out, line_no, line);
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Renders a single line as a DOT graph
+pub fn render_line(line: &LineState) -> String {
+    let mut out = HEADER.to_owned();
+    write_line(&mut out, 0, line);
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Rack, action};
+
+    #[test]
+    fn test_dot() {
+        let mut rack = Rack::new().with_recording_enabled();
+        let mut processor = rack.processor();
+        processor
+            .line("env \"unhealthy\"", 35)
+            .then_any(
+                action!("temp_high", |t| (t > 30).then_some(())),
+                action!("humidity_high", |_| None::<()>).with_recorded_input(&40),
+            )
+            .then(action!("alarm", |()| None::<()>))
+            .then(action!("notify", |()| Some(())));
+        rack.ingress(&mut processor);
+        let dot = super::render_snapshot(&rack.snapshot());
+        assert!(dot.starts_with("digraph logicline {"));
+        assert!(dot.contains("label=\"env \\\"unhealthy\\\"\";"));
+        assert!(
+            dot.contains("l0_in [shape=cds, style=filled, fillcolor=\"#dee2e6\", label=\"35\"];")
+        );
+        assert!(dot.contains("l0_s0_1 [label=\"humidity_high\\n→ 40\", fillcolor=\"#f4a3a3\""));
+        // OR branches are joined at the next step
+        assert!(dot.contains("l0_s0_0 -> l0_s1_0;"));
+        assert!(dot.contains("l0_s0_1 -> l0_s1_0;"));
+        assert!(dot.contains("l0_s2_0 [label=\"notify\", fillcolor=\"#e9ecef\""));
+    }
+}
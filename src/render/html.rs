@@ -0,0 +1,265 @@
+use std::fmt::Write as _;
+
+use super::{Status, escape_xml, format_timestamp, input_label, line_steps};
+use crate::{InputKind, LineState, Snapshot, trace::TraceRecord};
+
+const STYLE: &str = "body{font-family:Helvetica,Arial,sans-serif;margin:20px;color:#212529;\
+background:#fff}h1{font-size:20px}h2{font-size:16px;margin:18px 0 4px}.input,.stats{color:#6c757d;\
+font-size:13px;font-weight:normal}details{border:1px solid #dee2e6;border-radius:4px;\
+margin:8px 0;padding:4px 12px}summary{cursor:pointer;font-weight:bold}svg{display:block;\
+margin:4px 0}.legend span{display:inline-block;padding:2px 8px;margin-right:6px;\
+border-radius:4px;font-size:13px}";
+
+const ROW_HEIGHT: usize = 48;
+const BOX_HEIGHT: usize = 36;
+const CHAR_WIDTH: usize = 7;
+const PADDING: usize = 10;
+const GAP: usize = 28;
+const RAIL_X: usize = 6;
+
+fn colors(status: Status) -> (&'static str, &'static str) {
+    match status {
+        Status::Passed => ("#b7e4c7", "#2d6a4f"),
+        Status::Failed => ("#f4a3a3", "#9d0208"),
+        Status::Skipped => ("#e9ecef", "#adb5bd"),
+    }
+}
+
+fn text_width(s: &str) -> usize {
+    s.chars().count() * CHAR_WIDTH
+}
+
+struct Element {
+    name: String,
+    input: Option<String>,
+    status: Status,
+}
+
+fn write_contacts(out: &mut String, columns: &[Vec<Element>], widths: &[usize]) {
+    let mut x = RAIL_X + GAP;
+    for (column, w) in columns.iter().zip(widths) {
+        for (row, element) in column.iter().enumerate() {
+            let (fill, stroke) = colors(element.status);
+            let y = row * ROW_HEIGHT + (ROW_HEIGHT - BOX_HEIGHT) / 2;
+            let cx = x + w / 2;
+            let _ = write!(
+                out,
+                "<g><title>{status}</title><rect x=\"{x}\" y=\"{y}\" width=\"{w}\" \
+                 height=\"{BOX_HEIGHT}\" rx=\"4\" fill=\"{fill}\" stroke=\"{stroke}\" \
+                 stroke-width=\"1.5\"/>",
+                status = element.status.as_str()
+            );
+            if let Some(ref input) = element.input {
+                let _ = write!(
+                    out,
+                    "<text x=\"{cx}\" y=\"{}\" font-size=\"12\" text-anchor=\"middle\">{}</text>\
+                     <text x=\"{cx}\" y=\"{}\" font-size=\"11\" fill=\"#495057\" \
+                     text-anchor=\"middle\">{}</text>",
+                    y + 15,
+                    escape_xml(&element.name),
+                    y + 29,
+                    escape_xml(input)
+                );
+            } else {
+                let _ = write!(
+                    out,
+                    "<text x=\"{cx}\" y=\"{}\" font-size=\"12\" text-anchor=\"middle\">{}</text>",
+                    y + 22,
+                    escape_xml(&element.name)
+                );
+            }
+            out.push_str("</g>");
+        }
+        x += w + GAP;
+    }
+}
+
+fn write_rung(out: &mut String, line: &LineState) {
+    let columns: Vec<Vec<Element>> = line_steps(line)
+        .into_iter()
+        .map(|step| {
+            step.into_iter()
+                .map(|(info, status)| Element {
+                    name: info.name().to_owned(),
+                    input: input_label(info.input()).map(|input| {
+                        if info.input_kind() == InputKind::External {
+                            format!("→ {}", input)
+                        } else {
+                            input
+                        }
+                    }),
+                    status,
+                })
+                .collect()
+        })
+        .collect();
+    let widths: Vec<usize> = columns
+        .iter()
+        .map(|column| {
+            column
+                .iter()
+                .map(|e| {
+                    text_width(&e.name).max(e.input.as_deref().map_or(0, text_width)) + 2 * PADDING
+                })
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let rows = columns.iter().map(Vec::len).max().unwrap_or(1).max(1);
+    let width = RAIL_X * 2 + GAP + widths.iter().map(|w| w + GAP).sum::<usize>();
+    let height = rows * ROW_HEIGHT;
+    let y0 = ROW_HEIGHT / 2;
+    let _ = write!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+         viewBox=\"0 0 {w} {h}\" font-family=\"Helvetica,Arial,sans-serif\">\
+         <g stroke=\"#495057\" stroke-width=\"2\">\
+         <line x1=\"{l}\" y1=\"0\" x2=\"{l}\" y2=\"{h}\"/>\
+         <line x1=\"{r}\" y1=\"0\" x2=\"{r}\" y2=\"{h}\"/>\
+         <line x1=\"{l}\" y1=\"{y0}\" x2=\"{r}\" y2=\"{y0}\"/>",
+        w = width,
+        h = height,
+        l = RAIL_X,
+        r = width - RAIL_X,
+        y0 = y0
+    );
+    // parallel branches
+    let mut x = RAIL_X + GAP;
+    for (column, w) in columns.iter().zip(&widths) {
+        if column.len() > 1 {
+            let (left, right) = (x - GAP / 2, x + w + GAP / 2);
+            let y_last = y0 + (column.len() - 1) * ROW_HEIGHT;
+            let _ = write!(
+                out,
+                "<line x1=\"{left}\" y1=\"{y0}\" x2=\"{left}\" y2=\"{y_last}\"/>\
+                 <line x1=\"{right}\" y1=\"{y0}\" x2=\"{right}\" y2=\"{y_last}\"/>"
+            );
+            for row in 1..column.len() {
+                let y = y0 + row * ROW_HEIGHT;
+                let _ = write!(
+                    out,
+                    "<line x1=\"{left}\" y1=\"{y}\" x2=\"{right}\" y2=\"{y}\"/>"
+                );
+            }
+        }
+        x += w + GAP;
+    }
+    out.push_str("</g>");
+    write_contacts(out, &columns, &widths);
+    out.push_str("</svg>\n");
+}
+
+fn write_lines(out: &mut String, snapshot: &Snapshot) {
+    for (name, line) in snapshot.lines() {
+        let _ = write!(out, "<h2>{}", escape_xml(name));
+        if let Some(input) = line.input().and_then(input_label) {
+            let _ = write!(
+                out,
+                " <span class=\"input\">({})</span>",
+                escape_xml(&input)
+            );
+        }
+        if let Some(counters) = snapshot
+            .stats()
+            .and_then(|s| s.line_stats(name))
+            .map(crate::LineStats::counters)
+        {
+            let _ = write!(
+                out,
+                " <span class=\"stats\">executions: {}, passes: {}, fails: {}, transitions: {}\
+                 </span>",
+                counters.executions(),
+                counters.passes(),
+                counters.fails(),
+                counters.transitions()
+            );
+        }
+        out.push_str("</h2>\n");
+        write_rung(out, line);
+    }
+}
+
+fn write_header(out: &mut String, title: &str) {
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n<div class=\"legend\">"
+    );
+    for status in [Status::Passed, Status::Failed, Status::Skipped] {
+        let (fill, stroke) = colors(status);
+        let _ = write!(
+            out,
+            "<span style=\"background:{fill};border:1px solid {stroke}\">{}</span>",
+            status.as_str()
+        );
+    }
+    out.push_str("</div>\n");
+}
+
+/// Renders a snapshot into a standalone HTML document (inline CSS, SVG rungs), which can be viewed
+/// offline, e.g. attached to maintenance tickets
+pub fn render_snapshot(snapshot: &Snapshot) -> String {
+    let mut out = String::new();
+    write_header(&mut out, "Logic Line state");
+    write_lines(&mut out, snapshot);
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Renders a short history (trace records) into a standalone HTML document. The records are
+/// rendered as collapsible sections, the most recent one first (expanded)
+pub fn render_history(records: &[TraceRecord]) -> String {
+    let mut out = String::new();
+    write_header(&mut out, "Logic Line history");
+    for (i, record) in records.iter().rev().enumerate() {
+        let failed = record
+            .snapshot()
+            .lines()
+            .values()
+            .filter(|l| !l.passed())
+            .count();
+        let _ = writeln!(
+            out,
+            "<details{}><summary>{} UTC <span class=\"stats\">({} lines, {} not passed)</span>\
+             </summary>",
+            if i == 0 { " open" } else { "" },
+            format_timestamp(record.timestamp(), ' '),
+            record.snapshot().lines().len(),
+            failed
+        );
+        write_lines(&mut out, record.snapshot());
+        out.push_str("</details>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Rack, action, trace::TraceRecord};
+
+    #[test]
+    fn test_html() {
+        let mut rack = Rack::new().with_recording_enabled();
+        let mut processor = rack.processor();
+        processor
+            .line("temp<critical>", 31)
+            .then_any(
+                action!("temp_high", |t| (t > 30).then_some(())),
+                action!("temp_critical", |t| (t > 40).then_some(())),
+            )
+            .then(action!("alarm", |()| Some(())));
+        rack.ingress(&mut processor);
+        let html = super::render_snapshot(&rack.snapshot());
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<h2>temp&lt;critical&gt; <span class=\"input\">(31)</span></h2>"));
+        assert_eq!(html.matches("<rect").count(), 3);
+        assert!(html.contains("fill=\"#f4a3a3\""));
+        let history = super::render_history(&[
+            TraceRecord::new(0.0, rack.snapshot()),
+            TraceRecord::new(1_760_000_000.5, rack.snapshot()),
+        ]);
+        assert!(history.contains("<details open><summary>2025-10-09 08:53:20.500 UTC"));
+        assert!(history.contains("<details><summary>1970-01-01 00:00:00.000 UTC"));
+    }
+}
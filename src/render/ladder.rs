@@ -0,0 +1,178 @@
+use super::{Status, input_label, line_steps};
+use crate::{InputKind, LineState, Snapshot, StepStateInfo};
+
+const ANSI_RESET: &str = "\x1b[0m";
+
+fn ansi_color(status: Status) -> &'static str {
+    match status {
+        Status::Passed => "\x1b[32m",
+        Status::Failed => "\x1b[31m",
+        Status::Skipped => "\x1b[2m",
+    }
+}
+
+struct Element {
+    text: String,
+    status: Status,
+}
+
+impl Element {
+    fn new(info: &StepStateInfo, status: Status, coil: bool) -> Self {
+        let mut text = info.name().to_owned();
+        if let Some(input) = input_label(info.input()) {
+            text.push('(');
+            if info.input_kind() == InputKind::External {
+                text.push_str("\\->");
+            }
+            text.push_str(&input);
+            text.push(')');
+        }
+        if status == Status::Failed {
+            text.push_str(" !");
+        }
+        let text = if coil {
+            format!("( {} )", text)
+        } else {
+            format!("[ {} ]", text)
+        };
+        Self { text, status }
+    }
+    fn width(&self) -> usize {
+        self.text.chars().count()
+    }
+}
+
+/// Text ladder-diagram renderer for terminals and log files.
+///
+/// Each line is rendered as a rung between two rails, steps are rendered as contacts, `OR` steps
+/// as parallel branches and the last single step as a coil. Failed steps are marked with `!`
+/// (similarly to the [`LineState`] `Display` implementation), the elements can be additionally
+/// colored with ANSI escape codes:
+///
+/// ```text
+/// fan_on (31)
+/// |--+--[ temp_high(31) ]--------+--( fan_on )--|
+/// |  +--[ temp_critical(31) ! ]--+              |
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Ladder {
+    colors: bool,
+}
+
+impl Ladder {
+    /// Creates a new renderer (without colors)
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Enables/disables ANSI colors (passed elements are green, failed are red, skipped are dim)
+    pub fn with_colors(mut self, colors: bool) -> Self {
+        self.colors = colors;
+        self
+    }
+    fn paint(&self, element: &Element, out: &mut String) {
+        if self.colors {
+            out.push_str(ansi_color(element.status));
+            out.push_str(&element.text);
+            out.push_str(ANSI_RESET);
+        } else {
+            out.push_str(&element.text);
+        }
+    }
+    /// Renders a single line as a ladder rung
+    pub fn render_line(&self, line: &LineState) -> String {
+        let steps = line_steps(line);
+        let step_count = steps.len();
+        let columns: Vec<Vec<Element>> = steps
+            .into_iter()
+            .enumerate()
+            .map(|(step_no, step)| {
+                let coil = step_no + 1 == step_count && step.len() == 1;
+                step.into_iter()
+                    .map(|(info, status)| Element::new(info, status, coil))
+                    .collect()
+            })
+            .collect();
+        let rows = columns.iter().map(Vec::len).max().unwrap_or(1).max(1);
+        let mut out = line.name().to_owned();
+        if let Some(input) = line.input().and_then(input_label) {
+            out.push_str(" (");
+            out.push_str(&input);
+            out.push(')');
+        }
+        out.push('\n');
+        for row in 0..rows {
+            out.push('|');
+            for column in &columns {
+                let width = column.iter().map(Element::width).max().unwrap_or_default();
+                let (left, right, wire) = match (column.len() > 1, row) {
+                    (false, 0) => ("--", "", '-'),
+                    (true, 0) => ("--+--", "--+", '-'),
+                    (true, r) if r < column.len() => ("  +--", "--+", '-'),
+                    (true, _) => ("     ", "   ", ' '),
+                    (false, _) => ("  ", "", ' '),
+                };
+                out.push_str(left);
+                if let Some(element) = column.get(row) {
+                    self.paint(element, &mut out);
+                    out.extend(std::iter::repeat_n(wire, width - element.width()));
+                } else {
+                    out.extend(std::iter::repeat_n(' ', width));
+                }
+                out.push_str(right);
+            }
+            out.push_str(if row == 0 { "--|\n" } else { "  |\n" });
+        }
+        out
+    }
+    /// Renders a snapshot, the rungs are separated with empty lines
+    pub fn render_snapshot(&self, snapshot: &Snapshot) -> String {
+        let mut out = String::new();
+        for (i, line) in snapshot.lines().values().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            out.push_str(&self.render_line(line));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Ladder;
+    use crate::{Rack, action};
+
+    #[test]
+    fn test_ladder() {
+        let mut rack = Rack::new().with_recording_enabled();
+        let mut processor = rack.processor();
+        processor
+            .line("fan_on", 31)
+            .then_any(
+                action!("temp_high", |t| (t > 30).then_some(())),
+                action!("temp_critical", |t| (t > 40).then_some(())),
+            )
+            .then(action!("fan_on", |()| Some(())));
+        processor
+            .line("fan_off", 31)
+            .then(action!("temp_low", |t| (t < 25).then_some(())))
+            .then(action!("fan_off", |()| Some(())));
+        rack.ingress(&mut processor);
+        let ladder = Ladder::new().render_snapshot(&rack.snapshot());
+        assert_eq!(
+            ladder,
+            "fan_off (31)
+|--[ temp_low(31) ! ]--( fan_off )--|
+
+fan_on (31)
+|--+--[ temp_high(31) ]--------+--( fan_on )--|
+|  +--[ temp_critical(31) ! ]--+              |
+"
+        );
+        let colored = Ladder::new()
+            .with_colors(true)
+            .render_line(rack.line_state("fan_off").unwrap());
+        assert!(colored.contains("\x1b[31m[ temp_low(31) ! ]\x1b[0m"));
+        assert!(colored.contains("\x1b[2m( fan_off )\x1b[0m"));
+    }
+}
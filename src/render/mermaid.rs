@@ -0,0 +1,133 @@
+use std::fmt::Write as _;
+
+use super::{Status, input_label, line_steps};
+use crate::{InputKind, LineState, Snapshot};
+
+const CLASS_DEFS: &str = "  classDef passed fill:#b7e4c7,stroke:#2d6a4f
+  classDef failed fill:#f4a3a3,stroke:#9d0208
+  classDef skipped fill:#e9ecef,stroke:#adb5bd,color:#6c757d
+";
+
+fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '#' => result.push_str("#35;"),
+            '"' => result.push_str("#quot;"),
+            '<' => result.push_str("#lt;"),
+            '>' => result.push_str("#gt;"),
+            '\n' => result.push(' '),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Renders a single line as a Mermaid `flowchart LR` diagram. `OR` steps are rendered as parallel
+/// paths, the nodes are styled according to the step pass state (`passed`, `failed` and `skipped`
+/// classes).
+pub fn render_line(line: &LineState) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "---\ntitle: \"{}\"\n---", escape(line.name()));
+    out.push_str("flowchart LR\n");
+    match line.input().and_then(input_label) {
+        Some(input) => {
+            let _ = writeln!(out, "  in([\"{}\"])", escape(&input));
+        }
+        None => out.push_str("  in(( ))\n"),
+    }
+    let mut prev = vec!["in".to_owned()];
+    let mut classes: [(Status, Vec<String>); 3] = [
+        (Status::Passed, Vec::new()),
+        (Status::Failed, Vec::new()),
+        (Status::Skipped, Vec::new()),
+    ];
+    for (step_no, step) in line_steps(line).into_iter().enumerate() {
+        let mut current = Vec::with_capacity(step.len());
+        for (action_no, (info, status)) in step.into_iter().enumerate() {
+            let id = format!("s{}_{}", step_no, action_no);
+            let mut label = escape(info.name());
+            if let Some(input) = input_label(info.input()) {
+                label.push_str("<br/>");
+                if info.input_kind() == InputKind::External {
+                    label.push_str("→ ");
+                }
+                label.push_str(&escape(&input));
+            }
+            let _ = writeln!(out, "  {}[\"{}\"]", id, label);
+            for p in &prev {
+                let _ = writeln!(out, "  {} --> {}", p, id);
+            }
+            if let Some((_, ids)) = classes.iter_mut().find(|(s, _)| *s == status) {
+                ids.push(id.clone());
+            }
+            current.push(id);
+        }
+        if !current.is_empty() {
+            prev = current;
+        }
+    }
+    out.push_str(CLASS_DEFS);
+    for (status, ids) in classes {
+        if !ids.is_empty() {
+            let _ = writeln!(out, "  class {} {}", ids.join(","), status.as_str());
+        }
+    }
+    out
+}
+
+/// Renders a snapshot as a Markdown document with a Mermaid flowchart block per line, which can
+/// be pasted into wiki pages or issues
+pub fn render_snapshot(snapshot: &Snapshot) -> String {
+    let mut out = String::new();
+    for (i, line) in snapshot.lines().values().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let _ = writeln!(out, "```mermaid\n{}```", render_line(line));
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Rack, action};
+
+    #[test]
+    fn test_mermaid() {
+        let mut rack = Rack::new().with_recording_enabled();
+        let mut processor = rack.processor();
+        processor
+            .line("fan_on", 31)
+            .then_any(
+                action!("temp_high", |t| (t > 30).then_some(())),
+                action!("temp_critical", |t| (t > 40).then_some(())),
+            )
+            .then(action!("fan_on", |()| Some(())));
+        rack.ingress(&mut processor);
+        let snapshot = rack.snapshot();
+        let mermaid = super::render_line(snapshot.line_state("fan_on").unwrap());
+        assert_eq!(
+            mermaid,
+            "---
+title: \"fan_on\"
+---
+flowchart LR
+  in([\"31\"])
+  s0_0[\"temp_high<br/>31\"]
+  in --> s0_0
+  s0_1[\"temp_critical<br/>31\"]
+  in --> s0_1
+  s1_0[\"fan_on\"]
+  s0_0 --> s1_0
+  s0_1 --> s1_0
+  classDef passed fill:#b7e4c7,stroke:#2d6a4f
+  classDef failed fill:#f4a3a3,stroke:#9d0208
+  classDef skipped fill:#e9ecef,stroke:#adb5bd,color:#6c757d
+  class s0_0,s1_0 passed
+  class s0_1 failed
+"
+        );
+        assert!(super::render_snapshot(&snapshot).starts_with("```mermaid\n---\n"));
+    }
+}
@@ -0,0 +1,245 @@
+use std::{collections::BTreeSet, fmt::Write as _};
+
+use super::{escape_xml, format_timestamp, identifier};
+use crate::{LineState, Snapshot, StepStateInfo};
+
+const XHTML_NS: &str = "http://www.w3.org/1999/xhtml";
+
+const ELEMENT_WIDTH: usize = 21;
+const ELEMENT_HEIGHT: usize = 20;
+const ROW_HEIGHT: usize = 40;
+const COLUMN_WIDTH: usize = 100;
+const RAIL_X: usize = 20;
+const RAIL_WIDTH: usize = 3;
+const RUNG_GAP: usize = 40;
+
+struct Variable {
+    name: String,
+    documentation: String,
+}
+
+/// Ladder Diagram POU body, collected from line states
+#[derive(Default)]
+struct Pou {
+    variables: Vec<Variable>,
+    variable_names: BTreeSet<String>,
+    body: String,
+    next_id: usize,
+    next_y: usize,
+}
+
+impl Pou {
+    fn local_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id
+    }
+    /// Declares a BOOL variable with a unique name
+    fn variable(&mut self, name: String, documentation: String) -> String {
+        let mut unique = name.clone();
+        let mut n = 1;
+        while !self.variable_names.insert(unique.clone()) {
+            unique = format!("{}_{}", name, n);
+            n += 1;
+        }
+        self.variables.push(Variable {
+            name: unique.clone(),
+            documentation,
+        });
+        unique
+    }
+    fn write_connections(&mut self, refs: &[usize]) {
+        self.body
+            .push_str("<connectionPointIn><relPosition x=\"0\" y=\"10\"/>");
+        for r in refs {
+            let _ = write!(self.body, "<connection refLocalId=\"{}\"/>", r);
+        }
+        self.body.push_str("</connectionPointIn>");
+    }
+    fn write_element(
+        &mut self,
+        tag: &str,
+        x: usize,
+        y: usize,
+        refs: &[usize],
+        variable: &str,
+    ) -> usize {
+        let id = self.local_id();
+        let _ = write!(
+            self.body,
+            "<{tag} localId=\"{id}\" width=\"{ELEMENT_WIDTH}\" height=\"{ELEMENT_HEIGHT}\" \
+             negated=\"false\"><position x=\"{x}\" y=\"{y}\"/>"
+        );
+        self.write_connections(refs);
+        let _ = writeln!(
+            self.body,
+            "<connectionPointOut><relPosition x=\"{ELEMENT_WIDTH}\" y=\"10\"/>\
+             </connectionPointOut><variable>{}</variable></{tag}>",
+            escape_xml(variable)
+        );
+        id
+    }
+    fn push_line(&mut self, line: &LineState) {
+        let line_id = identifier(line.name());
+        let mut steps: Vec<Vec<&StepStateInfo>> = line.steps().iter().map(|s| s.info()).collect();
+        // the last single step becomes a coil, otherwise the coil is the line pass state
+        let coil = match steps.last() {
+            Some(last) if last.len() == 1 => {
+                let info = steps.pop().unwrap().remove(0);
+                self.variable(
+                    format!("{}_{}", line_id, identifier(info.name())),
+                    info.name().to_owned(),
+                )
+            }
+            _ => self.variable(format!("{}_passed", line_id), "line passed".to_owned()),
+        };
+        let rows = steps.iter().map(Vec::len).max().unwrap_or(1).max(1);
+        let top = self.next_y;
+        self.next_y += rows * ROW_HEIGHT + RUNG_GAP;
+        let comment_id = self.local_id();
+        let _ = writeln!(
+            self.body,
+            "<comment localId=\"{comment_id}\" width=\"{}\" height=\"{}\">\
+             <position x=\"{RAIL_X}\" y=\"{top}\"/><content><xhtml:p xmlns:xhtml=\"{XHTML_NS}\">\
+             {}</xhtml:p></content></comment>",
+            COLUMN_WIDTH * 2,
+            RUNG_GAP / 2,
+            escape_xml(line.name())
+        );
+        let top = top + RUNG_GAP / 2;
+        let rail_height = rows * ROW_HEIGHT;
+        let left_rail = self.local_id();
+        let _ = writeln!(
+            self.body,
+            "<leftPowerRail localId=\"{left_rail}\" width=\"{RAIL_WIDTH}\" height=\"{rail_height}\">\
+             <position x=\"{RAIL_X}\" y=\"{top}\"/><connectionPointOut formalParameter=\"\">\
+             <relPosition x=\"{RAIL_WIDTH}\" y=\"{}\"/></connectionPointOut></leftPowerRail>",
+            ROW_HEIGHT / 2
+        );
+        let mut prev = vec![left_rail];
+        let mut x = RAIL_X + COLUMN_WIDTH / 2;
+        for infos in &steps {
+            let mut current = Vec::with_capacity(infos.len());
+            for (row, info) in infos.iter().enumerate() {
+                let variable = self.variable(
+                    format!("{}_{}", line_id, identifier(info.name())),
+                    info.name().to_owned(),
+                );
+                let y = top + row * ROW_HEIGHT + (ROW_HEIGHT - ELEMENT_HEIGHT) / 2;
+                current.push(self.write_element("contact", x, y, &prev, &variable));
+            }
+            prev = current;
+            x += COLUMN_WIDTH;
+        }
+        let y = top + (ROW_HEIGHT - ELEMENT_HEIGHT) / 2;
+        let coil_id = self.write_element("coil", x, y, &prev, &coil);
+        let right_rail = self.local_id();
+        let _ = write!(
+            self.body,
+            "<rightPowerRail localId=\"{right_rail}\" width=\"{RAIL_WIDTH}\" \
+             height=\"{rail_height}\"><position x=\"{}\" y=\"{top}\"/>",
+            x + COLUMN_WIDTH / 2
+        );
+        self.write_connections(&[coil_id]);
+        self.body.push_str("</rightPowerRail>\n");
+    }
+    fn render(self, name: &str) -> String {
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <project xmlns=\"http://www.plcopen.org/xml/tc6_0201\" \
+             xmlns:xhtml=\"{XHTML_NS}\">\n\
+             <fileHeader companyName=\"\" productName=\"logicline\" productVersion=\"{}\" \
+             creationDateTime=\"{}\"/>\n\
+             <contentHeader name=\"{name}\"><coordinateInfo>\
+             <fbd><scaling x=\"1\" y=\"1\"/></fbd><ld><scaling x=\"1\" y=\"1\"/></ld>\
+             <sfc><scaling x=\"1\" y=\"1\"/></sfc></coordinateInfo></contentHeader>\n\
+             <types><dataTypes/><pous>\n<pou name=\"{name}\" pouType=\"program\">\n\
+             <interface><localVars>\n",
+            env!("CARGO_PKG_VERSION"),
+            format_timestamp(crate::stats::now(), 'T'),
+            name = escape_xml(name)
+        );
+        for variable in self.variables {
+            let _ = writeln!(
+                out,
+                "<variable name=\"{}\"><type><BOOL/></type><documentation>\
+                 <xhtml:p>{}</xhtml:p></documentation></variable>",
+                escape_xml(&variable.name),
+                escape_xml(&variable.documentation)
+            );
+        }
+        out.push_str("</localVars></interface>\n<body><LD>\n");
+        out.push_str(&self.body);
+        out.push_str(
+            "</LD></body>\n</pou>\n</pous></types>\n<instances><configurations/></instances>\n\
+             </project>\n",
+        );
+        out
+    }
+}
+
+/// Exports a snapshot as a PLCopen TC6 XML project with a single Ladder Diagram program POU, so
+/// the logic can be opened in standard PLC editors for review.
+///
+/// Each line becomes a rung (titled with a comment), steps become contacts, `OR` steps become
+/// parallel branches and the last single step becomes a coil (if the last step is an `OR` one,
+/// a `<line>_passed` coil is added). Each action gets a `BOOL` variable `<line>_<action>`,
+/// documented with the original action name.
+pub fn render_snapshot(snapshot: &Snapshot, pou_name: &str) -> String {
+    let mut pou = Pou::default();
+    for line in snapshot.lines().values() {
+        pou.push_line(line);
+    }
+    pou.render(pou_name)
+}
+
+/// Exports a single line as a PLCopen TC6 XML project, see [`render_snapshot`]
+pub fn render_line(line: &LineState, pou_name: &str) -> String {
+    let mut pou = Pou::default();
+    pou.push_line(line);
+    pou.render(pou_name)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Rack, action};
+
+    #[test]
+    fn test_plcopen() {
+        let mut rack = Rack::new().with_recording_enabled();
+        let mut processor = rack.processor();
+        processor
+            .line("fan_on", 31)
+            .then_any(
+                action!("temp_high", |t| (t > 30).then_some(())),
+                action!("temp<critical>", |t| (t > 40).then_some(())),
+            )
+            .then(action!("fan_on", |()| Some(())));
+        rack.ingress(&mut processor);
+        let xml = super::render_snapshot(&rack.snapshot(), "rules");
+        assert!(xml.contains("<pou name=\"rules\" pouType=\"program\">"));
+        assert!(xml.contains(
+            "<variable name=\"fan_on_temp_critical\"><type><BOOL/></type><documentation>\
+             <xhtml:p>temp&lt;critical&gt;</xhtml:p></documentation></variable>"
+        ));
+        // parallel branches start at the left rail and join at the coil
+        assert!(xml.contains(
+            "<contact localId=\"3\" width=\"21\" height=\"20\" negated=\"false\">\
+             <position x=\"70\" y=\"30\"/><connectionPointIn><relPosition x=\"0\" y=\"10\"/>\
+             <connection refLocalId=\"2\"/></connectionPointIn>"
+        ));
+        assert!(xml.contains(
+            "<contact localId=\"4\" width=\"21\" height=\"20\" negated=\"false\">\
+             <position x=\"70\" y=\"70\"/>"
+        ));
+        assert!(xml.contains(
+            "<connection refLocalId=\"3\"/><connection refLocalId=\"4\"/></connectionPointIn>\
+             <connectionPointOut><relPosition x=\"21\" y=\"10\"/></connectionPointOut>\
+             <variable>fan_on_fan_on</variable></coil>"
+        ));
+        assert!(
+            xml.contains("<connection refLocalId=\"5\"/></connectionPointIn></rightPowerRail>")
+        );
+    }
+}
@@ -0,0 +1,328 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+};
+
+use serde_json::Value;
+
+use super::{identifier, input_label};
+use crate::{InputKind, LineState, Snapshot};
+
+fn comment(s: &str) -> String {
+    s.replace("(*", "( *").replace("*)", "* )")
+}
+
+/// Elementary data type of a recorded input, `None` for null and structured inputs (generic
+/// types, such as `ANY`, are not allowed for user-declared inputs, so structured inputs are left
+/// out and must be defined manually)
+fn data_type(value: &Value) -> Option<&'static str> {
+    match value {
+        Value::Null | Value::Array(_) | Value::Object(_) => None,
+        Value::Bool(_) => Some("BOOL"),
+        Value::Number(n) if n.is_i64() => Some("LINT"),
+        Value::Number(n) if n.is_u64() => Some("ULINT"),
+        Value::Number(_) => Some("LREAL"),
+        Value::String(_) => Some("STRING"),
+    }
+}
+
+fn is_structured(value: &Value) -> bool {
+    matches!(value, Value::Array(_) | Value::Object(_))
+}
+
+const STRUCTURED_INPUT_TODO: &str = "(* TODO: structured input IN, define its data type *)";
+
+/// Structured Text program skeleton, collected from line states
+#[derive(Default)]
+struct Program {
+    // action functions with the input data types
+    functions: BTreeMap<String, Option<&'static str>>,
+    // action functions with structured inputs
+    structured: BTreeSet<String>,
+    lines: Vec<String>,
+}
+
+impl Program {
+    fn collect_functions(&mut self, line: &LineState) {
+        for info in line.steps().iter().flat_map(|s| s.info()) {
+            let input_type = data_type(info.input());
+            let name = identifier(info.name());
+            if is_structured(info.input()) {
+                self.structured.insert(name.clone());
+            }
+            let entry = self.functions.entry(name).or_default();
+            if entry.is_none() {
+                *entry = input_type;
+            }
+        }
+    }
+    fn function_type(&self, name: &str) -> Option<&'static str> {
+        self.functions.get(&identifier(name)).copied().flatten()
+    }
+    fn push_line(&mut self, line: &LineState) {
+        let steps: Vec<_> = line.steps().iter().map(|s| s.info()).collect();
+        let mut vars = String::new();
+        let mut body = String::new();
+        let line_input_type = line.input().and_then(data_type).or_else(|| {
+            steps.first().and_then(|infos| {
+                infos
+                    .iter()
+                    .filter(|i| i.input_kind() == InputKind::Flow)
+                    .find_map(|i| self.function_type(i.name()))
+            })
+        });
+        for (step_no, infos) in steps.iter().enumerate() {
+            let names = infos
+                .iter()
+                .map(|i| comment(i.name()))
+                .collect::<Vec<_>>()
+                .join(" OR ");
+            let _ = writeln!(vars, "    STEP_{} : BOOL; (* {} *)", step_no, names);
+            let flow_var = if step_no == 0 {
+                "IN".to_owned()
+            } else {
+                let flow_var = format!("STEP_{}_IN", step_no);
+                if let Some(t) = infos
+                    .iter()
+                    .filter(|i| i.input_kind() == InputKind::Flow)
+                    .find_map(|i| self.function_type(i.name()))
+                {
+                    let _ = writeln!(
+                        vars,
+                        "    {} : {}; (* output of STEP_{} *)",
+                        flow_var,
+                        t,
+                        step_no - 1
+                    );
+                }
+                flow_var
+            };
+            let mut calls = Vec::with_capacity(infos.len());
+            for (action_no, info) in infos.iter().enumerate() {
+                let function = identifier(info.name());
+                let arg = match (self.function_type(info.name()), info.input_kind()) {
+                    (None, _) => String::new(),
+                    (Some(_), InputKind::Flow) => flow_var.clone(),
+                    (Some(t), InputKind::External) => {
+                        let var = format!("STEP_{}_{}_IN", step_no, action_no);
+                        let _ = writeln!(vars, "    {} : {}; (* external input *)", var, t);
+                        var
+                    }
+                };
+                calls.push(format!("{}({})", function, arg));
+            }
+            let expr = calls.join(" OR ");
+            if step_no == 0 {
+                let _ = writeln!(body, "STEP_0 := {};", expr);
+            } else {
+                let _ = writeln!(
+                    body,
+                    "STEP_{n} := FALSE;\nIF STEP_{p} THEN\n    STEP_{n} := {e};\nEND_IF;",
+                    n = step_no,
+                    p = step_no - 1,
+                    e = expr
+                );
+            }
+        }
+        let mut out = String::new();
+        let _ = writeln!(out, "(* {} *)", comment(line.name()));
+        let _ = writeln!(out, "FUNCTION_BLOCK FB_{}", identifier(line.name()));
+        if line_input_type.is_none() && line.input().is_some_and(is_structured) {
+            let _ = writeln!(out, "{}", STRUCTURED_INPUT_TODO);
+        }
+        if let Some(t) = line_input_type {
+            out.push_str("VAR_INPUT\n");
+            match line.input().and_then(input_label) {
+                Some(recorded) => {
+                    let _ = writeln!(
+                        out,
+                        "    IN : {}; (* recorded: {} *)",
+                        t,
+                        comment(&recorded)
+                    );
+                }
+                None => {
+                    let _ = writeln!(out, "    IN : {};", t);
+                }
+            }
+            out.push_str("END_VAR\n");
+        }
+        out.push_str("VAR_OUTPUT\n    PASSED : BOOL;\nEND_VAR\n");
+        if !vars.is_empty() {
+            let _ = write!(out, "VAR\n{}END_VAR\n", vars);
+        }
+        out.push_str(&body);
+        if steps.is_empty() {
+            out.push_str("PASSED := TRUE;\n");
+        } else {
+            let _ = writeln!(out, "PASSED := STEP_{};", steps.len() - 1);
+        }
+        out.push_str("END_FUNCTION_BLOCK\n");
+        self.lines.push(out);
+    }
+    fn render(self) -> String {
+        let mut out = format!(
+            "(* Generated by logicline {} from the recorded line structure *)\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        for (name, input_type) in self.functions {
+            let _ = writeln!(out, "\nFUNCTION {} : BOOL", name);
+            if let Some(t) = input_type {
+                let _ = writeln!(out, "VAR_INPUT\n    IN : {};\nEND_VAR", t);
+            } else if self.structured.contains(&name) {
+                let _ = writeln!(out, "{}", STRUCTURED_INPUT_TODO);
+            }
+            let _ = writeln!(
+                out,
+                "(* TODO: implement *)\n{} := FALSE;\nEND_FUNCTION",
+                name
+            );
+        }
+        for line in self.lines {
+            out.push('\n');
+            out.push_str(&line);
+        }
+        out
+    }
+}
+
+/// Exports the recorded structure of the snapshot lines as IEC 61131-3 Structured Text skeleton
+/// code, so the logic can be reviewed by PLC engineers.
+///
+/// Each line becomes a function block `FB_<line>` with the line input (`IN`) and `PASSED` output,
+/// each action becomes a `BOOL` function stub, called in the line step order. `OR` steps are
+/// exported as `OR` expressions, the next steps are called only if the previous ones are passed.
+/// Input data types are guessed from the recorded inputs (`BOOL`, `LINT`, `LREAL`, `STRING`),
+/// structured inputs are left out with `TODO` comments and must be defined manually.
+pub fn render_snapshot(snapshot: &Snapshot) -> String {
+    let mut program = Program::default();
+    for line in snapshot.lines().values() {
+        program.collect_functions(line);
+    }
+    for line in snapshot.lines().values() {
+        program.push_line(line);
+    }
+    program.render()
+}
+
+/// Exports a single line as Structured Text skeleton code (a function block and its action
+/// function stubs), see [`render_snapshot`]
+pub fn render_line(line: &LineState) -> String {
+    let mut program = Program::default();
+    program.collect_functions(line);
+    program.push_line(line);
+    program.render()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Rack, action};
+
+    #[test]
+    fn test_st() {
+        let mut rack = Rack::new().with_recording_enabled();
+        let mut processor = rack.processor();
+        processor
+            .line("fan on", 31.5)
+            .then_any(
+                action!("temp_high", |t| (t > 30.0).then_some(t)),
+                action!("temp-critical", |t| (t > 40.0).then_some(t)),
+            )
+            .then(action!("humidity_ok", |_| Some(())).with_recorded_input(&true))
+            .then(action!("fan_on", |()| Some(())));
+        rack.ingress(&mut processor);
+        let st = super::render_line(rack.line_state("fan on").unwrap());
+        let expected = "
+FUNCTION fan_on : BOOL
+(* TODO: implement *)
+fan_on := FALSE;
+END_FUNCTION
+
+FUNCTION humidity_ok : BOOL
+VAR_INPUT
+    IN : BOOL;
+END_VAR
+(* TODO: implement *)
+humidity_ok := FALSE;
+END_FUNCTION
+
+FUNCTION temp_critical : BOOL
+VAR_INPUT
+    IN : LREAL;
+END_VAR
+(* TODO: implement *)
+temp_critical := FALSE;
+END_FUNCTION
+
+FUNCTION temp_high : BOOL
+VAR_INPUT
+    IN : LREAL;
+END_VAR
+(* TODO: implement *)
+temp_high := FALSE;
+END_FUNCTION
+
+(* fan on *)
+FUNCTION_BLOCK FB_fan_on
+VAR_INPUT
+    IN : LREAL; (* recorded: 31.5 *)
+END_VAR
+VAR_OUTPUT
+    PASSED : BOOL;
+END_VAR
+VAR
+    STEP_0 : BOOL; (* temp_high OR temp-critical *)
+    STEP_1 : BOOL; (* humidity_ok *)
+    STEP_1_0_IN : BOOL; (* external input *)
+    STEP_2 : BOOL; (* fan_on *)
+END_VAR
+STEP_0 := temp_high(IN) OR temp_critical(IN);
+STEP_1 := FALSE;
+IF STEP_0 THEN
+    STEP_1 := humidity_ok(STEP_1_0_IN);
+END_IF;
+STEP_2 := FALSE;
+IF STEP_1 THEN
+    STEP_2 := fan_on();
+END_IF;
+PASSED := STEP_2;
+END_FUNCTION_BLOCK
+";
+        assert_eq!(st.split_once('\n').unwrap().1, expected);
+    }
+
+    #[test]
+    fn test_st_structured() {
+        let mut rack = Rack::new().with_recording_enabled();
+        let mut processor = rack.processor();
+        processor
+            .line("fan_on", serde_json::json!({"temp": 31.5}))
+            .then(action!("temp_high", |t: serde_json::Value| {
+                (t["temp"].as_f64()? > 30.0).then_some(())
+            }));
+        rack.ingress(&mut processor);
+        let st = super::render_line(rack.line_state("fan_on").unwrap());
+        let expected = "
+FUNCTION temp_high : BOOL
+(* TODO: structured input IN, define its data type *)
+(* TODO: implement *)
+temp_high := FALSE;
+END_FUNCTION
+
+(* fan_on *)
+FUNCTION_BLOCK FB_fan_on
+(* TODO: structured input IN, define its data type *)
+VAR_OUTPUT
+    PASSED : BOOL;
+END_VAR
+VAR
+    STEP_0 : BOOL; (* temp_high *)
+END_VAR
+STEP_0 := temp_high();
+PASSED := STEP_0;
+END_FUNCTION_BLOCK
+";
+        assert_eq!(st.split_once('\n').unwrap().1, expected);
+        assert!(!st.contains("ANY"));
+    }
+}
@@ -0,0 +1,410 @@
+use std::{io, path::Path, time::Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Mutex, Snapshot,
+    trace::{TraceReader, TraceRecord},
+};
+
+/// The minimum playback speed
+pub const MIN_SPEED: f64 = 0.001;
+/// The maximum playback speed
+pub const MAX_SPEED: f64 = 1000.0;
+
+/// Replays recorded traces (see [`crate::trace`]) as if they were live, with seek, play, pause and
+/// speed control.
+///
+/// With the `exporter` feature enabled, the replay can be served with the built-in exporter (see
+/// [`Replay::install_exporter_on`]), so the recorded history can be analyzed with the same
+/// visualization tools as the live program state.
+pub struct Replay {
+    records: Vec<TraceRecord>,
+    playback: Mutex<Playback>,
+}
+
+struct Playback {
+    playing: bool,
+    speed: f64,
+    position: f64,
+    anchor: Instant,
+}
+
+/// Replay status
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReplayStatus {
+    /// Is the replay playing
+    pub playing: bool,
+    /// Playback speed (1.0 = real time)
+    pub speed: f64,
+    /// Current position (UNIX timestamp, seconds)
+    pub position: f64,
+    /// The first record timestamp
+    pub start: f64,
+    /// The last record timestamp
+    pub end: f64,
+    /// Current frame (record) number
+    pub frame: usize,
+    /// Total number of frames (records)
+    pub frames: usize,
+}
+
+impl Replay {
+    /// Creates a new replay from trace records. The replay is paused at the first record
+    pub fn new(records: impl IntoIterator<Item = TraceRecord>) -> Self {
+        let mut records: Vec<TraceRecord> = records.into_iter().collect();
+        records.sort_by(|a, b| a.timestamp().total_cmp(&b.timestamp()));
+        let position = records.first().map_or(0.0, TraceRecord::timestamp);
+        Self {
+            records,
+            playback: Mutex::new(Playback {
+                playing: false,
+                speed: 1.0,
+                position,
+                anchor: Instant::now(),
+            }),
+        }
+    }
+    /// Loads a trace file together with its rotated files
+    pub fn from_trace(path: impl AsRef<Path>) -> io::Result<Self> {
+        let records = TraceReader::open_rotated(path)?.collect::<io::Result<Vec<_>>>()?;
+        Ok(Self::new(records))
+    }
+    /// Trace records
+    pub fn records(&self) -> &[TraceRecord] {
+        &self.records
+    }
+    fn start(&self) -> f64 {
+        self.records.first().map_or(0.0, TraceRecord::timestamp)
+    }
+    fn end(&self) -> f64 {
+        self.records.last().map_or(0.0, TraceRecord::timestamp)
+    }
+    /// Updates the position of a playing replay
+    fn sync(&self, playback: &mut Playback) {
+        if playback.playing {
+            let now = Instant::now();
+            playback.position += now.duration_since(playback.anchor).as_secs_f64() * playback.speed;
+            playback.anchor = now;
+            if playback.position >= self.end() {
+                playback.position = self.end();
+                playback.playing = false;
+            }
+        }
+    }
+    fn frame_at(&self, position: f64) -> usize {
+        self.records
+            .partition_point(|r| r.timestamp() <= position)
+            .saturating_sub(1)
+    }
+    /// Current replay status
+    pub fn status(&self) -> ReplayStatus {
+        let mut playback = self.playback.lock();
+        self.sync(&mut playback);
+        ReplayStatus {
+            playing: playback.playing,
+            speed: playback.speed,
+            position: playback.position,
+            start: self.start(),
+            end: self.end(),
+            frame: self.frame_at(playback.position),
+            frames: self.records.len(),
+        }
+    }
+    /// Starts/resumes playing. If the replay is at the end, it is started from the beginning
+    pub fn play(&self) {
+        let mut playback = self.playback.lock();
+        self.sync(&mut playback);
+        if playback.position >= self.end() {
+            playback.position = self.start();
+        }
+        playback.playing = true;
+        playback.anchor = Instant::now();
+    }
+    /// Pauses the replay
+    pub fn pause(&self) {
+        let mut playback = self.playback.lock();
+        self.sync(&mut playback);
+        playback.playing = false;
+    }
+    /// Seeks to the specified position (UNIX timestamp, seconds), the position is clamped to the
+    /// recorded time range (infinities included). NaN positions are ignored
+    pub fn seek(&self, position: f64) {
+        if position.is_nan() {
+            return;
+        }
+        let mut playback = self.playback.lock();
+        playback.position = position.clamp(self.start(), self.end());
+        playback.anchor = Instant::now();
+    }
+    /// Seeks to the specified frame (record) number
+    pub fn seek_frame(&self, frame: usize) {
+        if let Some(record) = self.records.get(frame).or_else(|| self.records.last()) {
+            self.seek(record.timestamp());
+        }
+    }
+    /// Sets the playback speed (1.0 = real time), the speed is clamped to
+    /// [`MIN_SPEED`]..=[`MAX_SPEED`]. NaN speeds are ignored
+    pub fn set_speed(&self, speed: f64) {
+        if speed.is_nan() {
+            return;
+        }
+        let mut playback = self.playback.lock();
+        self.sync(&mut playback);
+        playback.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+    /// Snapshot of the current frame
+    pub fn snapshot(&self) -> Snapshot {
+        let mut playback = self.playback.lock();
+        self.sync(&mut playback);
+        self.records
+            .get(self.frame_at(playback.position))
+            .map(|r| r.snapshot().clone())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "exporter")]
+impl Replay {
+    /// Creates an exporter builder for the replay. Besides the standard exporter endpoints, the
+    /// following ones are provided:
+    ///
+    /// * `GET /replay` - the replay status
+    ///
+    /// * `POST /replay/play`, `POST /replay/pause` - start/pause playing
+    ///
+    /// * `POST /replay/seek?t=<timestamp>`, `?offset=<seconds>` (from the start) or
+    ///   `?frame=<number>` - seek
+    ///
+    /// * `POST /replay/speed?value=<speed>` - set the playback speed
+    ///
+    /// All the endpoints respond with the replay status. The `POST` endpoints must be enabled
+    /// with [`crate::exporter::Exporter::with_control`] (authentication is required) and are
+    /// protected from cross-site requests the same way as the rack control ones.
+    pub fn exporter(self: std::sync::Arc<Self>) -> crate::exporter::Exporter {
+        crate::exporter::Exporter::from_handler(self)
+    }
+
+    /// Installs the exporter (HTTP server) for the replay on the specified address, see
+    /// [`Replay::exporter`] (the control endpoints are disabled)
+    pub fn install_exporter_on<A: std::net::ToSocketAddrs>(
+        self: std::sync::Arc<Self>,
+        addr: A,
+    ) -> Result<crate::exporter::ExporterHandle, Box<dyn std::error::Error>> {
+        self.exporter().spawn(addr)
+    }
+
+    fn control(&self, path: &str, request: &tiny_http::Request) -> Option<()> {
+        let param = |name: &str| {
+            crate::exporter::query(request)
+                .find(|(k, _)| *k == name)
+                .and_then(|(_, v)| v.parse::<f64>().ok())
+                .filter(|v| v.is_finite())
+        };
+        match path {
+            "play" => self.play(),
+            "pause" => self.pause(),
+            "seek" => {
+                if let Some(t) = param("t") {
+                    self.seek(t);
+                } else if let Some(offset) = param("offset") {
+                    self.seek(Some(self.start() + offset).filter(|t| t.is_finite())?);
+                } else {
+                    let frame = param("frame").filter(|v| *v >= 0.0)?;
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                    self.seek_frame(frame as usize);
+                }
+            }
+            "speed" => self.set_speed(param("value").filter(|v| *v > 0.0)?),
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+#[cfg(feature = "exporter")]
+impl crate::exporter::Handler for std::sync::Arc<Replay> {
+    fn snapshot(&self) -> Snapshot {
+        Replay::snapshot(self)
+    }
+    fn handle(
+        &self,
+        request: tiny_http::Request,
+        exporter: &crate::exporter::Exporter,
+        cors: &[tiny_http::Header],
+    ) -> Option<tiny_http::Request> {
+        use crate::exporter::{json_response, path, respond};
+        use tiny_http::{Method, Response};
+
+        let p = path(&request);
+        if p == "/replay" && request.method() == &Method::Get {
+            respond(request, json_response(&self.status()), cors);
+            return None;
+        }
+        let Some(command) = p.strip_prefix("/replay/").map(ToOwned::to_owned) else {
+            return Some(request);
+        };
+        if request.method() != &Method::Post {
+            respond(request, Response::empty(405), cors);
+        } else if let Err(code) = exporter.check_control(&request) {
+            respond(request, Response::empty(code), cors);
+        } else if self.control(&command, &request).is_some() {
+            respond(request, json_response(&self.status()), cors);
+        } else {
+            respond(request, Response::empty(400), cors);
+        }
+        None
+    }
+    fn has_control(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Replay;
+    use crate::{Snapshot, trace::TraceRecord};
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_replay_seek() {
+        let records = (0..10).map(|i| TraceRecord::new(100.0 + f64::from(i), Snapshot::default()));
+        let replay = Replay::new(records);
+        assert_eq!(replay.status().frame, 0);
+        replay.seek(104.5);
+        assert_eq!(replay.status().frame, 4);
+        replay.seek_frame(7);
+        assert_eq!(replay.status().position, 107.0);
+        replay.seek(1000.0);
+        let status = replay.status();
+        assert_eq!(status.frame, 9);
+        assert!(!status.playing);
+        // playing from the end restarts the replay
+        replay.set_speed(0.001);
+        replay.play();
+        let status = replay.status();
+        assert!(status.playing);
+        assert_eq!(status.frame, 0);
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)]
+    fn test_replay_invalid_values() {
+        use super::{MAX_SPEED, MIN_SPEED};
+
+        let records = (0..10).map(|i| TraceRecord::new(100.0 + f64::from(i), Snapshot::default()));
+        let replay = Replay::new(records);
+        replay.seek(104.0);
+        replay.seek(f64::NAN);
+        assert_eq!(replay.status().position, 104.0);
+        replay.seek(f64::INFINITY);
+        assert_eq!(replay.status().position, 109.0);
+        replay.seek(f64::NEG_INFINITY);
+        assert_eq!(replay.status().position, 100.0);
+        replay.set_speed(f64::INFINITY);
+        assert_eq!(replay.status().speed, MAX_SPEED);
+        replay.set_speed(-1.0);
+        assert_eq!(replay.status().speed, MIN_SPEED);
+        replay.set_speed(f64::NAN);
+        assert_eq!(replay.status().speed, MIN_SPEED);
+    }
+
+    #[cfg(feature = "exporter")]
+    #[test]
+    fn test_replay_exporter() {
+        use std::{net::SocketAddr, sync::Arc};
+
+        use crate::{
+            exporter::{Auth, Cors, test::request},
+            replay::ReplayStatus,
+        };
+
+        fn call(addr: SocketAddr, method: &str, path: &str, headers: &str) -> String {
+            request(
+                addr,
+                &format!(
+                    "{} {} HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer secret\r\n\
+                     {}Content-Length: 0\r\nConnection: close\r\n\r\n",
+                    method, path, headers
+                ),
+            )
+        }
+        fn get(addr: SocketAddr, path: &str) -> String {
+            call(addr, "GET", path, "")
+        }
+        fn post(addr: SocketAddr, path: &str) -> String {
+            call(addr, "POST", path, "Content-Type: application/json\r\n")
+        }
+        fn status(response: &str) -> ReplayStatus {
+            assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+            serde_json::from_str(response.split_once("\r\n\r\n").unwrap().1).unwrap()
+        }
+
+        let records = (0..10).map(|i| TraceRecord::new(100.0 + f64::from(i), Snapshot::default()));
+        let replay = Arc::new(Replay::new(records));
+        // the control endpoints are disabled by default and require authentication
+        let exporter = replay.clone().install_exporter_on("127.0.0.1:0").unwrap();
+        let addr = exporter.local_addr().unwrap();
+        assert_eq!(status(&get(addr, "/replay")).frames, 10);
+        assert!(post(addr, "/replay/play").starts_with("HTTP/1.1 404"));
+        exporter.stop().unwrap();
+        assert!(
+            replay
+                .clone()
+                .exporter()
+                .with_control()
+                .spawn("127.0.0.1:0")
+                .is_err()
+        );
+        let exporter = replay
+            .clone()
+            .exporter()
+            .with_auth(Auth::new().with_bearer_token("secret"))
+            .with_cors(Cors::new().with_origin("https://scada.example.com"))
+            .with_control()
+            .spawn("127.0.0.1:0")
+            .unwrap();
+        let addr = exporter.local_addr().unwrap();
+        assert_eq!(status(&get(addr, "/replay")).frames, 10);
+        assert_eq!(status(&post(addr, "/replay/seek?t=104.5")).frame, 4);
+        assert_eq!(status(&post(addr, "/replay/seek?offset=2")).frame, 2);
+        assert_eq!(status(&post(addr, "/replay/seek?frame=7")).frame, 7);
+        for path in [
+            "/replay/seek?t=NaN",
+            "/replay/seek?offset=inf",
+            "/replay/seek",
+            "/replay/speed?value=NaN",
+            "/replay/speed?value=0",
+        ] {
+            assert!(post(addr, path).starts_with("HTTP/1.1 400"), "{}", path);
+        }
+        assert_eq!(status(&get(addr, "/replay")).frame, 7);
+        assert!(status(&post(addr, "/replay/speed?value=2")).speed > 1.0);
+        assert!(status(&post(addr, "/replay/play")).playing);
+        assert!(!status(&post(addr, "/replay/pause")).playing);
+        assert!(post(addr, "/replay/unknown").starts_with("HTTP/1.1 400"));
+        assert!(get(addr, "/replay/play").starts_with("HTTP/1.1 405"));
+        // cross-site requests are rejected
+        assert!(call(addr, "POST", "/replay/play", "").starts_with("HTTP/1.1 415"));
+        assert!(
+            call(
+                addr,
+                "POST",
+                "/replay/play",
+                "Content-Type: application/json\r\nOrigin: https://evil.example.com\r\n"
+            )
+            .starts_with("HTTP/1.1 403")
+        );
+        assert!(!status(&get(addr, "/replay")).playing);
+        assert!(
+            call(
+                addr,
+                "POST",
+                "/replay/play",
+                "Content-Type: application/json\r\nOrigin: https://scada.example.com\r\n"
+            )
+            .starts_with("HTTP/1.1 200")
+        );
+        exporter.stop().unwrap();
+    }
+}
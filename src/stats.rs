@@ -0,0 +1,253 @@
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::LineState;
+
+/// Returns the current system time as UNIX timestamp (seconds)
+pub(crate) fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
+/// Pass/fail counters of a line or a step
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Counters {
+    /// Number of executions
+    executions: u64,
+    /// Number of passes
+    passes: u64,
+    /// Number of fails
+    fails: u64,
+    /// Number of pass state transitions
+    transitions: u64,
+    /// The last pass state
+    passed: Option<bool>,
+    /// The last transition time (UNIX timestamp)
+    last_transition: Option<f64>,
+}
+
+impl Counters {
+    fn record(&mut self, passed: bool, timestamp: f64) {
+        self.executions += 1;
+        if passed {
+            self.passes += 1;
+        } else {
+            self.fails += 1;
+        }
+        if self.passed.is_some_and(|prev| prev != passed) {
+            self.transitions += 1;
+            self.last_transition = Some(timestamp);
+        }
+        self.passed = Some(passed);
+    }
+    /// Number of times the line/step has been recorded
+    pub fn executions(&self) -> u64 {
+        self.executions
+    }
+    /// Number of times the line/step has been passed
+    pub fn passes(&self) -> u64 {
+        self.passes
+    }
+    /// Number of times the line/step has not been passed
+    pub fn fails(&self) -> u64 {
+        self.fails
+    }
+    /// Number of pass/fail state changes
+    pub fn transitions(&self) -> u64 {
+        self.transitions
+    }
+    /// The last recorded pass state
+    pub fn passed(&self) -> Option<bool> {
+        self.passed
+    }
+    /// The last pass/fail state change time (UNIX timestamp, seconds)
+    pub fn last_transition(&self) -> Option<f64> {
+        self.last_transition
+    }
+    /// Pass ratio (0.0 - 1.0), `None` if never executed
+    #[allow(clippy::cast_precision_loss)]
+    pub fn pass_ratio(&self) -> Option<f64> {
+        if self.executions == 0 {
+            None
+        } else {
+            Some(self.passes as f64 / self.executions as f64)
+        }
+    }
+}
+
+/// Statistics of a single step. For `OR` steps, each action has got own statistics
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct StepStats {
+    /// Step name
+    name: Cow<'static, str>,
+    #[serde(flatten)]
+    counters: Counters,
+}
+
+impl StepStats {
+    /// Step name
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+    /// Step counters
+    pub fn counters(&self) -> &Counters {
+        &self.counters
+    }
+}
+
+/// Statistics of a logical line
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct LineStats {
+    #[serde(flatten)]
+    counters: Counters,
+    /// Step statistics (`OR` steps are flattened)
+    steps: Vec<StepStats>,
+}
+
+impl LineStats {
+    /// Line counters (the line is passed if all its steps are passed)
+    pub fn counters(&self) -> &Counters {
+        &self.counters
+    }
+    /// Step statistics, in the order of the line steps (`OR` steps are flattened)
+    pub fn steps(&self) -> &[StepStats] {
+        &self.steps
+    }
+    /// Statistics of the step by its name (the first one if there are several with the same name)
+    pub fn step(&self, name: &str) -> Option<&StepStats> {
+        self.steps.iter().find(|s| s.name == name)
+    }
+    fn record(&mut self, line: &LineState, timestamp: f64) {
+        self.counters.record(line.passed(), timestamp);
+        let mut count = 0;
+        let infos = line.steps().iter().flat_map(|s| s.info());
+        for (i, info) in infos.enumerate() {
+            count = i + 1;
+            // line structure may be changed between the cycles (e.g. in case of conditional
+            // steps), reset the step counters in this case
+            if self.steps.get(i).is_none_or(|s| s.name != info.name()) {
+                self.steps.truncate(i);
+                self.steps.push(StepStats {
+                    name: info.name().to_owned().into(),
+                    counters: Counters::default(),
+                });
+            }
+            self.steps[i].counters.record(info.passed(), timestamp);
+        }
+        // the line may become shorter, drop the stale trailing steps
+        self.steps.truncate(count);
+    }
+}
+
+/// Per-line and per-step pass/fail statistics, accumulated by [`crate::Rack`] on ingress
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RackStats {
+    /// Line statistics
+    lines: BTreeMap<Cow<'static, str>, LineStats>,
+}
+
+impl RackStats {
+    /// Statistics of the line
+    pub fn line_stats(&self, name: &str) -> Option<&LineStats> {
+        self.lines.get(name)
+    }
+    /// Lines statistics map
+    pub fn lines(&self) -> &BTreeMap<Cow<'static, str>, LineStats> {
+        &self.lines
+    }
+    /// Resets all the counters
+    pub fn reset(&mut self) {
+        self.lines.clear();
+    }
+    pub(crate) fn filtered(&self, lines: &BTreeMap<Cow<'static, str>, LineState>) -> Self {
+        RackStats {
+            lines: self
+                .lines
+                .iter()
+                .filter(|(name, _)| lines.contains_key(*name))
+                .map(|(name, stats)| (name.clone(), stats.clone()))
+                .collect(),
+        }
+    }
+    pub(crate) fn record<'a, I>(&mut self, lines: I)
+    where
+        I: IntoIterator<Item = (&'a Cow<'static, str>, &'a LineState)>,
+    {
+        let timestamp = now();
+        for (name, line) in lines {
+            if let Some(stats) = self.lines.get_mut(name) {
+                stats.record(line, timestamp);
+            } else {
+                let mut stats = LineStats::default();
+                stats.record(line, timestamp);
+                self.lines.insert(name.clone(), stats);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Rack, action};
+
+    #[test]
+    fn test_stats() {
+        let mut rack = Rack::new().with_recording_enabled().with_stats_enabled();
+        for temp in [31, 20, 35, 36] {
+            let mut processor = rack.processor();
+            processor
+                .line("fan_on", temp)
+                .then_any(
+                    action!("temp_high", |t| (t > 30).then_some(())),
+                    action!("temp_critical", |t| (t > 35).then_some(())),
+                )
+                .then(action!("fan_on", |()| Some(())));
+            rack.ingress(&mut processor);
+        }
+        let stats = rack.stats().unwrap().line_stats("fan_on").unwrap();
+        assert_eq!(stats.counters().executions(), 4);
+        assert_eq!(stats.counters().passes(), 3);
+        assert_eq!(stats.counters().fails(), 1);
+        assert_eq!(stats.counters().transitions(), 2);
+        assert!(stats.counters().last_transition().is_some());
+        assert_eq!(stats.steps().len(), 3);
+        let temp_critical = stats.step("temp_critical").unwrap().counters();
+        assert_eq!(temp_critical.passes(), 1);
+        assert_eq!(temp_critical.fails(), 3);
+        assert_eq!(temp_critical.transitions(), 1);
+        assert_eq!(temp_critical.pass_ratio(), Some(0.25));
+        assert!(rack.snapshot().stats().is_some());
+        rack.reset_stats();
+        assert!(rack.stats().unwrap().lines().is_empty());
+    }
+
+    #[test]
+    fn test_stats_line_shrinks() {
+        let mut rack = Rack::new().with_recording_enabled().with_stats_enabled();
+        for cooling in [true, false] {
+            let mut processor = rack.processor();
+            let line = processor
+                .line("fan_on", 31)
+                .then(action!("temp_high", |t| (t > 30).then_some(())));
+            if cooling {
+                line.then(action!("cooling", |()| Some(())));
+            }
+            rack.ingress(&mut processor);
+        }
+        let stats = rack.stats().unwrap().line_stats("fan_on").unwrap();
+        assert_eq!(stats.steps().len(), 1);
+        assert!(stats.step("cooling").is_none());
+        assert_eq!(stats.step("temp_high").unwrap().counters().executions(), 2);
+    }
+}
@@ -0,0 +1,257 @@
+use std::{fmt::Write as _, fs, path::Path};
+
+use serde_json::Value;
+
+use crate::{InputKind, LineState, Snapshot, StepStateInfo};
+
+/// Golden snapshot directory, relative to the crate manifest directory (used by
+/// [`crate::assert_snapshot!`])
+pub const SNAPSHOT_DIR: &str = "tests/snapshots";
+
+/// If the environment variable is set (to any value except `0`), mismatched/missing golden files
+/// are updated instead of failing the assertion
+pub const UPDATE_ENV: &str = "LOGICLINE_UPDATE_SNAPSHOTS";
+
+/// Asserts that the snapshot matches the golden file `tests/snapshots/<name>.json` of the crate
+/// being tested, see [`assert_snapshot_file`]
+#[macro_export]
+macro_rules! assert_snapshot {
+    ($name: expr, $snapshot: expr) => {
+        $crate::testing::assert_snapshot_file(
+            ::std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join($crate::testing::SNAPSHOT_DIR)
+                .join(format!("{}.json", $name)),
+            &$snapshot,
+        )
+    };
+}
+
+pub use crate::assert_snapshot;
+
+fn format_input(input: &Value) -> String {
+    if input.is_null() {
+        "-".to_owned()
+    } else {
+        input.to_string()
+    }
+}
+
+fn format_passed(passed: bool) -> &'static str {
+    if passed { "passed" } else { "not passed" }
+}
+
+fn diff_action(out: &mut String, step_no: usize, expected: &StepStateInfo, actual: &StepStateInfo) {
+    if expected.passed() != actual.passed() {
+        let _ = writeln!(
+            out,
+            "    step {} {}: {} -> {}",
+            step_no,
+            expected.name(),
+            format_passed(expected.passed()),
+            format_passed(actual.passed())
+        );
+    }
+    if expected.input() != actual.input() || expected.input_kind() != actual.input_kind() {
+        let kind = |info: &StepStateInfo| match info.input_kind() {
+            InputKind::Flow => "",
+            InputKind::External => " (external)",
+        };
+        let _ = writeln!(
+            out,
+            "    step {} {} input: {}{} -> {}{}",
+            step_no,
+            expected.name(),
+            format_input(expected.input()),
+            kind(expected),
+            format_input(actual.input()),
+            kind(actual)
+        );
+    }
+}
+
+fn diff_line(out: &mut String, expected: &LineState, actual: &LineState) {
+    let mut line_out = String::new();
+    let expected_input = expected.input().unwrap_or(&Value::Null);
+    let actual_input = actual.input().unwrap_or(&Value::Null);
+    if expected_input != actual_input {
+        let _ = writeln!(
+            line_out,
+            "    input: {} -> {}",
+            format_input(expected_input),
+            format_input(actual_input)
+        );
+    }
+    if expected.passed() != actual.passed() {
+        let _ = writeln!(
+            line_out,
+            "    {} -> {}",
+            format_passed(expected.passed()),
+            format_passed(actual.passed())
+        );
+    }
+    let step_count = expected.steps().len().max(actual.steps().len());
+    for step_no in 0..step_count {
+        let expected_infos = expected.steps().get(step_no).map(|s| s.info());
+        let actual_infos = actual.steps().get(step_no).map(|s| s.info());
+        let names = |infos: &Option<Vec<&StepStateInfo>>| {
+            infos.as_ref().map(|infos| {
+                infos
+                    .iter()
+                    .map(|i| i.name().to_owned())
+                    .collect::<Vec<_>>()
+                    .join(" | ")
+            })
+        };
+        match (names(&expected_infos), names(&actual_infos)) {
+            (Some(e), Some(a)) if e == a => {
+                for (e, a) in expected_infos
+                    .unwrap_or_default()
+                    .into_iter()
+                    .zip(actual_infos.unwrap_or_default())
+                {
+                    diff_action(&mut line_out, step_no, e, a);
+                }
+            }
+            (e, a) => {
+                let _ = writeln!(
+                    line_out,
+                    "    step {}: {} -> {}",
+                    step_no,
+                    e.as_deref().unwrap_or("<none>"),
+                    a.as_deref().unwrap_or("<none>")
+                );
+            }
+        }
+    }
+    if !line_out.is_empty() {
+        let _ = writeln!(out, "  line {}:", expected.name());
+        out.push_str(&line_out);
+        let _ = writeln!(out, "    expected: {}", expected);
+        let _ = writeln!(out, "    actual:   {}", actual);
+    }
+}
+
+/// Compares two snapshots using the line state semantics (line presence, inputs, step structure,
+/// pass states and step inputs, statistics are ignored). Returns `None` if the snapshots match,
+/// otherwise a human-readable difference report
+pub fn diff(expected: &Snapshot, actual: &Snapshot) -> Option<String> {
+    let mut out = String::new();
+    for (name, expected_line) in expected.lines() {
+        match actual.lines().get(name) {
+            Some(actual_line) => diff_line(&mut out, expected_line, actual_line),
+            None => {
+                let _ = writeln!(out, "- line {}: missing", name);
+            }
+        }
+    }
+    for (name, actual_line) in actual.lines() {
+        if !expected.lines().contains_key(name) {
+            let _ = writeln!(out, "+ line {}: unexpected", name);
+            let _ = writeln!(out, "    actual:   {}", actual_line);
+        }
+    }
+    (!out.is_empty()).then_some(out)
+}
+
+fn golden(snapshot: &Snapshot) -> Snapshot {
+    Snapshot::new(snapshot.lines.clone(), None)
+}
+
+fn check_snapshot_file(path: &Path, actual: &Snapshot, update: bool) -> Result<(), String> {
+    let actual = golden(actual);
+    let update_file = || -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let mut data = serde_json::to_string_pretty(&actual).map_err(|e| e.to_string())?;
+        data.push('\n');
+        fs::write(path, data).map_err(|e| format!("unable to write {}: {}", path.display(), e))
+    };
+    let expected: Snapshot = match fs::read_to_string(path) {
+        Ok(data) => serde_json::from_str(&data)
+            .map_err(|e| format!("invalid golden file {}: {}", path.display(), e))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if update {
+                return update_file();
+            }
+            return Err(format!(
+                "golden file {} not found, set {}=1 to create it",
+                path.display(),
+                UPDATE_ENV
+            ));
+        }
+        Err(e) => return Err(format!("unable to read {}: {}", path.display(), e)),
+    };
+    match diff(&expected, &actual) {
+        None => Ok(()),
+        Some(_) if update => update_file(),
+        Some(d) => Err(format!(
+            "snapshot does not match {} (set {}=1 to update):\n{}",
+            path.display(),
+            UPDATE_ENV,
+            d
+        )),
+    }
+}
+
+/// Asserts that the snapshot matches the golden file (JSON-serialized snapshot, statistics are not
+/// stored). If [`UPDATE_ENV`] environment variable is set, the file is created/updated instead.
+///
+/// # Panics
+///
+/// Panics if the snapshot does not match (with the difference report, see [`diff`]), if the
+/// golden file is missing (and not being updated) or can not be read/written
+pub fn assert_snapshot_file(path: impl AsRef<Path>, actual: &Snapshot) {
+    let update = std::env::var_os(UPDATE_ENV).is_some_and(|v| v != "0");
+    if let Err(e) = check_snapshot_file(path.as_ref(), actual, update) {
+        panic!("{}", e);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Rack, Snapshot, action};
+
+    fn snapshot(temp: i32) -> Snapshot {
+        let mut rack = Rack::new().with_recording_enabled();
+        let mut processor = rack.processor();
+        processor
+            .line("fan_on", temp)
+            .then_any(
+                action!("temp_high", |t| (t > 30).then_some(())),
+                action!("temp_critical", |t| (t > 40).then_some(())),
+            )
+            .then(action!("fan_on", |()| Some(())));
+        rack.ingress(&mut processor);
+        rack.snapshot()
+    }
+
+    #[test]
+    fn test_golden_snapshot() {
+        let path = std::env::temp_dir()
+            .join(format!("logicline-golden-{}", std::process::id()))
+            .join("fan.json");
+        let _ = std::fs::remove_file(&path);
+        assert!(super::check_snapshot_file(&path, &snapshot(31), false).is_err());
+        super::check_snapshot_file(&path, &snapshot(31), true).unwrap();
+        super::check_snapshot_file(&path, &snapshot(31), false).unwrap();
+        let err = super::check_snapshot_file(&path, &snapshot(20), false).unwrap_err();
+        let report = err.split_once('\n').unwrap().1;
+        assert_eq!(
+            report,
+            "  line fan_on:
+    input: 31 -> 20
+    passed -> not passed
+    step 0 temp_high: passed -> not passed
+    step 0 temp_high input: 31 -> 20
+    step 0 temp_critical input: 31 -> 20
+    step 1 fan_on: passed -> not passed
+    expected: fan_on: ( temp_high(31) | temp_critical(31) ) ! -> fan_on
+    actual:   fan_on: ( temp_high(20) | temp_critical(20) ) ! -> fan_on
+"
+        );
+        super::check_snapshot_file(&path, &snapshot(20), true).unwrap();
+        super::check_snapshot_file(&path, &snapshot(20), false).unwrap();
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
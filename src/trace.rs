@@ -0,0 +1,504 @@
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+        mpsc,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{LineState, Snapshot};
+
+const DEFAULT_QUEUE_SIZE: usize = 1024;
+
+/// A single trace record: a snapshot of the rack lines, taken on ingress
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TraceRecord {
+    timestamp: f64,
+    snapshot: Snapshot,
+}
+
+impl TraceRecord {
+    /// Creates a new trace record
+    pub fn new(timestamp: f64, snapshot: Snapshot) -> Self {
+        Self {
+            timestamp,
+            snapshot,
+        }
+    }
+    /// Record time (UNIX timestamp, seconds)
+    pub fn timestamp(&self) -> f64 {
+        self.timestamp
+    }
+    /// Recorded snapshot
+    pub fn snapshot(&self) -> &Snapshot {
+        &self.snapshot
+    }
+    /// Converts the record into the snapshot
+    pub fn into_snapshot(self) -> Snapshot {
+        self.snapshot
+    }
+}
+
+/// Trace writing mode
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum TraceMode {
+    /// Write every ingressed cycle
+    #[default]
+    All,
+    /// Write a cycle only if line states have been changed since the previous written one
+    Changes,
+}
+
+enum Message {
+    Record(TraceRecord),
+    // line states, recorded by a rack on ingress, merged by the writer
+    Lines {
+        timestamp: f64,
+        lines: BTreeMap<Cow<'static, str>, LineState>,
+        // the lines are the full rack state, not an update
+        full: bool,
+        // the lines have been changed since the previous ingress
+        changed: bool,
+    },
+    Flush(mpsc::SyncSender<()>),
+}
+
+/// Trace writer configuration. Writes rack snapshots into a file in JSON Lines format, with
+/// optional size- or time-based rotation. The rotated files get numeric suffixes (`.1` is the
+/// most recent one).
+///
+/// The writer works in a background thread so ingress operations are never blocked by disk I/O.
+/// In case if the writer can not keep up, the records are dropped.
+#[derive(Debug, Clone)]
+pub struct TraceWriter {
+    path: PathBuf,
+    mode: TraceMode,
+    max_size: Option<u64>,
+    rotation_interval: Option<Duration>,
+    retention: usize,
+    queue_size: usize,
+}
+
+impl TraceWriter {
+    /// Creates a new trace writer configuration for the specified file path
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            mode: TraceMode::default(),
+            max_size: None,
+            rotation_interval: None,
+            retention: usize::MAX,
+            queue_size: DEFAULT_QUEUE_SIZE,
+        }
+    }
+    /// Sets the writing mode (default: all cycles)
+    pub fn with_mode(mut self, mode: TraceMode) -> Self {
+        self.mode = mode;
+        self
+    }
+    /// Rotates the file when its size exceeds the specified one (bytes)
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+    /// Rotates the file periodically
+    pub fn with_rotation_interval(mut self, interval: Duration) -> Self {
+        self.rotation_interval = Some(interval);
+        self
+    }
+    /// Sets the maximum number of rotated files to keep (default: unlimited)
+    pub fn with_retention(mut self, retention: usize) -> Self {
+        self.retention = retention;
+        self
+    }
+    /// Sets the writer queue size (default: 1024 records)
+    pub fn with_queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size;
+        self
+    }
+    /// Opens the file and spawns the writer thread. The thread is stopped when all the tracer
+    /// instances are dropped.
+    pub fn spawn(self) -> io::Result<Tracer> {
+        let (tx, rx) = mpsc::sync_channel(self.queue_size);
+        let mut file = TraceFile::open(self)?;
+        std::thread::Builder::new()
+            .name("ll-trace".to_owned())
+            .spawn(move || file.run(&rx))?;
+        Ok(Tracer {
+            tx,
+            dropped: <_>::default(),
+        })
+    }
+}
+
+/// A handle to a running trace writer, which can be attached to a rack (see
+/// [`crate::Rack::set_tracer`]). The handle can be cloned, all clones share the same writer.
+#[derive(Clone)]
+pub struct Tracer {
+    tx: mpsc::SyncSender<Message>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl std::fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tracer")
+            .field("dropped", &self.dropped())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tracer {
+    /// Sends a record to the writer, never blocks. In case if the writer queue is full or the
+    /// writer is stopped, the record is dropped
+    pub fn send(&self, record: TraceRecord) {
+        if self.tx.try_send(Message::Record(record)).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    /// Sends line states, recorded on ingress, to the writer, never blocks. Returns `false` if the
+    /// message has been dropped (the rack sends the full state next time)
+    pub(crate) fn send_lines(
+        &self,
+        timestamp: f64,
+        lines: BTreeMap<Cow<'static, str>, LineState>,
+        full: bool,
+        changed: bool,
+    ) -> bool {
+        let message = Message::Lines {
+            timestamp,
+            lines,
+            full,
+            changed,
+        };
+        if self.tx.try_send(message).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+    /// Number of records dropped because of the writer queue overflow
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+    /// Blocks until all the records sent before are written and flushed to the disk
+    pub fn flush(&self) -> io::Result<()> {
+        let (tx, rx) = mpsc::sync_channel(0);
+        self.tx
+            .send(Message::Flush(tx))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "trace writer stopped"))?;
+        rx.recv()
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "trace writer stopped"))
+    }
+}
+
+struct TraceFile {
+    config: TraceWriter,
+    writer: BufWriter<fs::File>,
+    size: u64,
+    opened_at: Instant,
+    last_lines: Option<String>,
+    // the rack state, merged from ingress updates
+    lines: BTreeMap<Cow<'static, str>, LineState>,
+}
+
+impl TraceFile {
+    fn open(config: TraceWriter) -> io::Result<Self> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            config,
+            writer: BufWriter::new(file),
+            size,
+            opened_at: Instant::now(),
+            last_lines: None,
+            lines: BTreeMap::new(),
+        })
+    }
+    fn run(&mut self, rx: &mpsc::Receiver<Message>) {
+        while let Ok(message) = rx.recv() {
+            self.process(message);
+            // drain the queue before flushing the buffer
+            while let Ok(message) = rx.try_recv() {
+                self.process(message);
+            }
+            let _ = self.writer.flush();
+        }
+    }
+    fn process(&mut self, message: Message) {
+        match message {
+            Message::Record(record) => {
+                // a failed record is lost, the writer tries again with the next one
+                let _ = self.write(&record);
+            }
+            Message::Lines {
+                timestamp,
+                lines,
+                full,
+                changed,
+            } => {
+                if full {
+                    self.lines = lines;
+                } else {
+                    self.lines.extend(lines);
+                }
+                if self.config.mode == TraceMode::Changes && !changed && !full {
+                    return;
+                }
+                let record = TraceRecord::new(timestamp, Snapshot::new(self.lines.clone(), None));
+                let _ = self.write(&record);
+            }
+            Message::Flush(ack) => {
+                let _ = self.writer.flush();
+                let _ = ack.send(());
+            }
+        }
+    }
+    fn write(&mut self, record: &TraceRecord) -> io::Result<()> {
+        if self.config.mode == TraceMode::Changes {
+            let lines = serde_json::to_string(record.snapshot.lines())?;
+            if self.last_lines.as_ref() == Some(&lines) {
+                return Ok(());
+            }
+            self.last_lines = Some(lines);
+        }
+        if self.rotation_required() {
+            self.rotate()?;
+        }
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+    fn rotation_required(&self) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        self.config.max_size.is_some_and(|max| self.size >= max)
+            || self
+                .config
+                .rotation_interval
+                .is_some_and(|interval| self.opened_at.elapsed() >= interval)
+    }
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        let path = &self.config.path;
+        let retention = self.config.retention;
+        if retention == 0 {
+            fs::remove_file(path)?;
+        } else {
+            let mut last = 1;
+            while last < retention && rotated_path(path, last).exists() {
+                last += 1;
+            }
+            // rename does not replace existing files on all platforms
+            let oldest = rotated_path(path, last);
+            if oldest.exists() {
+                fs::remove_file(oldest)?;
+            }
+            for i in (1..last).rev() {
+                fs::rename(rotated_path(path, i), rotated_path(path, i + 1))?;
+            }
+            fs::rename(path, rotated_path(path, 1))?;
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        self.writer = BufWriter::new(file);
+        self.size = 0;
+        self.opened_at = Instant::now();
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// Reads trace records from JSON Lines files, written by [`TraceWriter`]
+pub struct TraceReader {
+    files: std::vec::IntoIter<PathBuf>,
+    current: Option<io::Lines<BufReader<fs::File>>>,
+}
+
+impl TraceReader {
+    /// Opens a single trace file
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+        Ok(Self {
+            files: Vec::new().into_iter(),
+            current: Some(BufReader::new(file).lines()),
+        })
+    }
+    /// Opens a trace file together with its rotated files, the records are read in the
+    /// chronological order (the oldest rotated file first)
+    pub fn open_rotated(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut files = Vec::new();
+        let mut n = 1;
+        while rotated_path(path, n).exists() {
+            files.push(rotated_path(path, n));
+            n += 1;
+        }
+        files.reverse();
+        files.push(path.to_owned());
+        let mut files = files.into_iter();
+        let first = files.next().map(fs::File::open).transpose()?;
+        Ok(Self {
+            files,
+            current: first.map(|f| BufReader::new(f).lines()),
+        })
+    }
+}
+
+impl Iterator for TraceReader {
+    type Item = io::Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let lines = self.current.as_mut()?;
+            match lines.next() {
+                Some(Ok(line)) if line.trim().is_empty() => {}
+                Some(Ok(line)) => {
+                    return Some(serde_json::from_str(&line).map_err(io::Error::from));
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None => match self.files.next().map(fs::File::open)? {
+                    Ok(file) => self.current = Some(BufReader::new(file).lines()),
+                    Err(e) => return Some(Err(e)),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TraceMode, TraceReader, TraceWriter};
+    use crate::{Rack, action};
+
+    #[test]
+    fn test_trace_rotation() {
+        let dir = std::env::temp_dir().join(format!("ll-trace-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("trace.jsonl");
+        let tracer = TraceWriter::new(&path)
+            .with_mode(TraceMode::Changes)
+            .with_max_size(1)
+            .with_retention(2)
+            .spawn()
+            .unwrap();
+        let mut rack = Rack::new().with_recording_enabled();
+        rack.set_tracer(Some(tracer.clone()));
+        for temp in [20, 20, 31, 32, 20] {
+            let mut processor = rack.processor();
+            processor
+                .line("fan_on", temp)
+                .then(action!("temp_high", |t| (t > 30).then_some(())));
+            rack.ingress(&mut processor);
+        }
+        tracer.flush().unwrap();
+        // 4 changes, each one is written into a new file, 1 active and 2 rotated files are kept
+        assert!(!dir.join("trace.jsonl.3").exists());
+        let records = TraceReader::open_rotated(&path)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let inputs = records
+            .iter()
+            .map(|r| {
+                r.snapshot().line_state("fan_on").unwrap().steps()[0].info()[0]
+                    .input()
+                    .clone()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(inputs, [31, 32, 20]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_trace_merge() {
+        let dir = std::env::temp_dir().join(format!("ll-trace-merge-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("trace.jsonl");
+        let tracer = TraceWriter::new(&path)
+            .with_mode(TraceMode::Changes)
+            .spawn()
+            .unwrap();
+        let mut rack = Rack::new().with_recording_enabled();
+        let ingress = |rack: &mut Rack, name: &'static str, temp: i32| {
+            let mut processor = rack.processor();
+            processor
+                .line(name, temp)
+                .then(action!("temp_high", |t| (t > 30).then_some(())));
+            rack.ingress(&mut processor);
+        };
+        // the lines, recorded before the tracer is attached, are written as well
+        ingress(&mut rack, "fan1", 20);
+        rack.set_tracer(Some(tracer.clone()));
+        ingress(&mut rack, "fan2", 20);
+        ingress(&mut rack, "fan2", 20);
+        ingress(&mut rack, "fan1", 31);
+        rack.clear();
+        ingress(&mut rack, "fan2", 20);
+        tracer.flush().unwrap();
+        let lines = TraceReader::open(&path)
+            .unwrap()
+            .map(|r| {
+                r.unwrap()
+                    .snapshot()
+                    .lines()
+                    .keys()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [vec!["fan1", "fan2"], vec!["fan1", "fan2"], vec!["fan2"]]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_trace_rotation_replaces_oldest() {
+        let dir = std::env::temp_dir().join(format!("ll-trace-oldest-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("trace.jsonl");
+        std::fs::write(dir.join("trace.jsonl.1"), "").unwrap();
+        std::fs::write(&path, "\n").unwrap();
+        let tracer = TraceWriter::new(&path)
+            .with_max_size(1)
+            .with_retention(1)
+            .spawn()
+            .unwrap();
+        let mut rack = Rack::new()
+            .with_recording_enabled()
+            .with_tracer(tracer.clone());
+        let mut processor = rack.processor();
+        processor.line("fan_on", 31);
+        rack.ingress(&mut processor);
+        tracer.flush().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("trace.jsonl.1")).unwrap(),
+            "\n"
+        );
+        assert!(!dir.join("trace.jsonl.2").exists());
+        assert_eq!(TraceReader::open(&path).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use serde_json::Value;
+
+use crate::{LineState, trace::TraceRecord};
+
+const TIMESCALE_PER_SECOND: f64 = 1_000_000.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum SignalValue {
+    Bit(bool),
+    Real(f64),
+    Unknown,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum SignalKind {
+    LinePassed,
+    LineInput,
+    // step signals are ordered by the flattened step index
+    Step(usize, StepSignalKind),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum StepSignalKind {
+    Passed,
+    Input,
+}
+
+impl SignalKind {
+    fn is_real(self) -> bool {
+        matches!(
+            self,
+            SignalKind::LineInput | SignalKind::Step(_, StepSignalKind::Input)
+        )
+    }
+}
+
+struct Signal {
+    id: String,
+    name: String,
+    value: Option<SignalValue>,
+}
+
+#[derive(Default)]
+struct LineSignals {
+    // step names by the flattened step index
+    steps: BTreeMap<usize, String>,
+    signals: BTreeMap<SignalKind, Signal>,
+}
+
+/// Converts a VCD signal name into a valid identifier
+fn vcd_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+        .collect()
+}
+
+/// VCD short identifier code (printable ASCII, base-94)
+fn id_code(mut n: usize) -> String {
+    let mut code = String::new();
+    loop {
+        #[allow(clippy::cast_possible_truncation)]
+        code.push(char::from(b'!' + (n % 94) as u8));
+        n /= 94;
+        if n == 0 {
+            break code;
+        }
+        n -= 1;
+    }
+}
+
+fn numeric(value: &Value) -> Option<f64> {
+    value.as_f64()
+}
+
+fn line_values(line: &LineState) -> Vec<(SignalKind, SignalValue)> {
+    let mut values = vec![(SignalKind::LinePassed, SignalValue::Bit(line.passed()))];
+    if let Some(input) = line.input().and_then(numeric) {
+        values.push((SignalKind::LineInput, SignalValue::Real(input)));
+    }
+    let infos = line.steps().iter().flat_map(|s| s.info());
+    for (i, info) in infos.enumerate() {
+        values.push((
+            SignalKind::Step(i, StepSignalKind::Passed),
+            SignalValue::Bit(info.passed()),
+        ));
+        if let Some(input) = numeric(info.input()) {
+            values.push((
+                SignalKind::Step(i, StepSignalKind::Input),
+                SignalValue::Real(input),
+            ));
+        }
+    }
+    values
+}
+
+/// Writes recorded rack history as a VCD (Value Change Dump) file, which can be opened with
+/// waveform viewers, such as GTKWave.
+///
+/// Each line gets own scope with a 1-bit `passed` signal and 1-bit signals for its steps (`OR`
+/// steps are flattened). Numeric line and step inputs become real-valued signals (`<name>.input`).
+/// The time scale is 1 microsecond, the time is relative to the first record.
+pub fn write<W: Write>(mut writer: W, records: &[TraceRecord]) -> io::Result<()> {
+    let mut lines: BTreeMap<Cow<'static, str>, LineSignals> = BTreeMap::new();
+    // collect the signals
+    for record in records {
+        for (name, line) in record.snapshot().lines() {
+            let signals = lines.entry(name.clone()).or_default();
+            let infos = line.steps().iter().flat_map(|s| s.info());
+            for (i, info) in infos.enumerate() {
+                signals
+                    .steps
+                    .entry(i)
+                    .or_insert_with(|| vcd_name(info.name()));
+            }
+            for (kind, _) in line_values(line) {
+                signals.signals.entry(kind).or_insert_with(|| Signal {
+                    id: String::new(),
+                    name: String::new(),
+                    value: None,
+                });
+            }
+        }
+    }
+    let mut n = 0;
+    for signals in lines.values_mut() {
+        let steps = &signals.steps;
+        for (kind, signal) in &mut signals.signals {
+            signal.id = id_code(n);
+            n += 1;
+            // steps may have the same names, the index is added to keep signal names unique
+            let step_name = |i: &usize| {
+                let name = &steps[i];
+                if steps.values().filter(|n| *n == name).count() > 1 {
+                    format!("{}_{}", name, i)
+                } else {
+                    name.clone()
+                }
+            };
+            signal.name = match kind {
+                SignalKind::LinePassed => "passed".to_owned(),
+                SignalKind::LineInput => "input".to_owned(),
+                SignalKind::Step(i, StepSignalKind::Passed) => step_name(i),
+                SignalKind::Step(i, StepSignalKind::Input) => format!("{}.input", step_name(i)),
+            };
+        }
+    }
+    writeln!(
+        writer,
+        "$version logicline {} $end",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(writer, "$timescale 1us $end")?;
+    writeln!(writer, "$scope module rack $end")?;
+    for (name, signals) in &lines {
+        writeln!(writer, "$scope module {} $end", vcd_name(name))?;
+        for (kind, signal) in &signals.signals {
+            if kind.is_real() {
+                writeln!(writer, "$var real 64 {} {} $end", signal.id, signal.name)?;
+            } else {
+                writeln!(writer, "$var wire 1 {} {} $end", signal.id, signal.name)?;
+            }
+        }
+        writeln!(writer, "$upscope $end")?;
+    }
+    writeln!(writer, "$upscope $end")?;
+    writeln!(writer, "$enddefinitions $end")?;
+    let Some(start) = records.first().map(TraceRecord::timestamp) else {
+        return Ok(());
+    };
+    let mut last_time = None;
+    for record in records {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let time = ((record.timestamp() - start) * TIMESCALE_PER_SECOND).round() as u64;
+        let snapshot_lines = record.snapshot().lines();
+        for (name, signals) in &mut lines {
+            let mut values = snapshot_lines
+                .get(name)
+                .map(line_values)
+                .unwrap_or_default()
+                .into_iter()
+                .collect::<BTreeMap<_, _>>();
+            for (kind, signal) in &mut signals.signals {
+                let value = match values.remove(kind) {
+                    Some(value) => value,
+                    // non-numeric/missing inputs keep the previous value
+                    None if kind.is_real() => continue,
+                    None => SignalValue::Unknown,
+                };
+                if signal.value == Some(value) {
+                    continue;
+                }
+                if last_time != Some(time) {
+                    writeln!(writer, "#{}", time)?;
+                    last_time = Some(time);
+                }
+                match value {
+                    SignalValue::Bit(bit) => writeln!(writer, "{}{}", u8::from(bit), signal.id)?,
+                    SignalValue::Real(real) => writeln!(writer, "r{} {}", real, signal.id)?,
+                    SignalValue::Unknown => writeln!(writer, "x{}", signal.id)?,
+                }
+                signal.value = Some(value);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Converts recorded rack history into a VCD (Value Change Dump) string, see [`write`]
+pub fn to_string(records: &[TraceRecord]) -> String {
+    let mut buf = Vec::new();
+    // writing to a vector never fails
+    let _ = write(&mut buf, records);
+    String::from_utf8(buf).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Rack, action, trace::TraceRecord};
+
+    #[test]
+    fn test_vcd() {
+        let mut rack = Rack::new().with_recording_enabled();
+        let mut records = Vec::new();
+        for (i, temp) in [20.0, 31.0, 31.5, 20.0].into_iter().enumerate() {
+            let mut processor = rack.processor();
+            processor
+                .line("fan_on", temp)
+                .then(action!("temp_high", |t| (t > 30.0).then_some(())))
+                .then(action!("fan_on", |()| Some(())));
+            rack.ingress(&mut processor);
+            records.push(TraceRecord::new(
+                0.5 * f64::from(u8::try_from(i).unwrap()),
+                rack.snapshot(),
+            ));
+        }
+        let vcd = super::to_string(&records);
+        assert!(vcd.contains("$var wire 1 ! passed $end"));
+        assert!(vcd.contains("$var real 64 \" input $end"));
+        assert!(vcd.contains("$var wire 1 # temp_high $end"));
+        assert!(vcd.contains("$var real 64 $ temp_high.input $end"));
+        assert!(vcd.contains("$var wire 1 % fan_on $end"));
+        let changes = vcd.split("$enddefinitions $end\n").nth(1).unwrap();
+        assert_eq!(
+            changes,
+            "#0\n0!\nr20 \"\n0#\nr20 $\n0%\n\
+             #500000\n1!\nr31 \"\n1#\nr31 $\n1%\n\
+             #1000000\nr31.5 \"\nr31.5 $\n\
+             #1500000\n0!\nr20 \"\n0#\nr20 $\n0%\n"
+        );
+    }
+}